@@ -0,0 +1,10 @@
+// generated by build.rs from src/cell_state.rs. DO NOT EDIT.
+
+struct CellState {
+    uint stateFlags;
+    float flipProgress;
+    float placeProgress;
+};
+layout(std140) uniform BoardState {
+    CellState cells[144];
+} boardState;
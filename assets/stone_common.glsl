@@ -0,0 +1,34 @@
+// placement and motion of stones shared by stone and shadow rendering
+// (requires generated/board_state.glsl)
+
+// distance between cell centers on the 8x8 board stones are modeled for
+const float SPACE = 2.65;
+const float DROP_HEIGHT = 6.0;
+
+uniform int board_size;
+
+// stones shrink on larger boards so that the board keeps its size on screen
+float cellScale() {
+    return 8.0 / float(board_size);
+}
+
+bool cellPlaced(CellState c) {
+    return (int(c.stateFlags) & 0x80) != 0;
+}
+bool cellIsWhite(CellState c) {
+    return (int(c.stateFlags) & 0x01) != 0;
+}
+
+// center of the cell on the board plane
+vec2 cellCenter(int index) {
+    vec2 cell = vec2(index % board_size, index / board_size) - float(board_size) * 0.5 + 0.5;
+    return vec2(cell.x, -cell.y) * SPACE * cellScale();
+}
+
+// height above the resting position:
+// hop while flipping, and falling from above while being placed
+float stoneHeight(CellState c) {
+    float t = clamp(c.flipProgress, 0.0, 1.0);
+    float h = (4.0 * t * (1.0 - t)) * 4.0;
+    return h + (1.0 - clamp(c.placeProgress, 0.0, 1.0)) * DROP_HEIGHT;
+}
@@ -0,0 +1,261 @@
+//! Generates the GLSL and TypeScript declarations of `CellState`
+//! from `src/cell_state.rs`.
+//!
+//! The declarations are checked in, so that shaders and scripts are built
+//! from the source tree alone. The build fails if they are out of date, and
+//! `UPDATE_GENERATED=1 cargo build` rewrites them.
+
+use std::path::Path;
+
+const CELL_STATE_SOURCE: &'static str = "src/cell_state.rs";
+const GLSL_OUTPUT: &'static str = "assets/generated/board_state.glsl";
+const TS_OUTPUT: &'static str = "scripts/src/generated/cellState.ts";
+const UPDATE_ENV: &'static str = "UPDATE_GENERATED";
+const GENERATED_NOTICE: &'static str =
+    "// generated by build.rs from src/cell_state.rs. DO NOT EDIT.";
+
+#[derive(Clone, Copy)]
+enum ScalarType {
+    U32,
+    I32,
+    F32,
+}
+impl ScalarType {
+    fn from_rust_type(ty: &syn::Type) -> Self {
+        let name = match ty {
+            syn::Type::Path(p) => p
+                .path
+                .get_ident()
+                .map(|i| i.to_string())
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+
+        match name.as_str() {
+            "u32" => ScalarType::U32,
+            "i32" => ScalarType::I32,
+            "f32" => ScalarType::F32,
+            _ => panic!("Unsupported field type in CellState: {}", name),
+        }
+    }
+
+    fn glsl_name(self) -> &'static str {
+        match self {
+            ScalarType::U32 => "uint",
+            ScalarType::I32 => "int",
+            ScalarType::F32 => "float",
+        }
+    }
+    fn dataview_accessor(self) -> &'static str {
+        match self {
+            ScalarType::U32 => "Uint32",
+            ScalarType::I32 => "Int32",
+            ScalarType::F32 => "Float32",
+        }
+    }
+    /// std140 base alignment and size of the scalar
+    fn size(self) -> usize {
+        4
+    }
+}
+
+struct Field {
+    /// camelCase name used in GLSL and TypeScript
+    name: String,
+    ty: ScalarType,
+    offset: usize,
+}
+
+struct Layout {
+    fields: Vec<Field>,
+    size: usize,
+    array_stride: usize,
+    cell_count: usize,
+}
+
+fn round_up(x: usize, align: usize) -> usize {
+    (x + align - 1) / align * align
+}
+
+fn camel_case(snake: &str) -> String {
+    let mut parts = snake.split('_');
+    let mut s = parts.next().unwrap_or_default().to_owned();
+    for p in parts {
+        let mut cs = p.chars();
+        if let Some(c) = cs.next() {
+            s.extend(c.to_uppercase());
+            s.push_str(cs.as_str());
+        }
+    }
+    s
+}
+
+/// Evaluates a constant expression consisting of integer literals, + and *
+fn eval_usize(e: &syn::Expr) -> usize {
+    match e {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(i),
+            ..
+        }) => i.base10_parse().expect("invalid integer literal"),
+        syn::Expr::Paren(p) => eval_usize(&p.expr),
+        syn::Expr::Binary(b) => match b.op {
+            syn::BinOp::Add(_) => eval_usize(&b.left) + eval_usize(&b.right),
+            syn::BinOp::Mul(_) => eval_usize(&b.left) * eval_usize(&b.right),
+            _ => panic!("Unsupported operator in constant expression"),
+        },
+        _ => panic!("Unsupported constant expression"),
+    }
+}
+
+fn parse_layout(source: &str) -> Layout {
+    let file = syn::parse_file(source).expect("Failed to parse cell_state.rs");
+
+    let mut cell_count = None;
+    let mut fields = None;
+    for item in &file.items {
+        match item {
+            syn::Item::Const(c) if c.ident == "BOARD_CELL_COUNT" => {
+                cell_count = Some(eval_usize(&c.expr));
+            }
+            syn::Item::Struct(s) if s.ident == "CellState" => {
+                let mut offset = 0;
+                fields = Some(
+                    s.fields
+                        .iter()
+                        .map(|f| {
+                            let ty = ScalarType::from_rust_type(&f.ty);
+                            offset = round_up(offset, ty.size());
+                            let field = Field {
+                                name: camel_case(
+                                    &f.ident
+                                        .as_ref()
+                                        .expect("tuple struct is not supported")
+                                        .to_string(),
+                                ),
+                                ty,
+                                offset,
+                            };
+                            offset += ty.size();
+                            field
+                        })
+                        .collect::<Vec<_>>(),
+                );
+            }
+            _ => (),
+        }
+    }
+
+    let fields = fields.expect("no CellState struct defined");
+    let size = fields.last().map_or(0, |f| f.offset + f.ty.size());
+    Layout {
+        fields,
+        size,
+        // std140: array elements of structures are aligned to vec4
+        array_stride: round_up(size, 16),
+        cell_count: cell_count.expect("no BOARD_CELL_COUNT defined"),
+    }
+}
+
+fn generate_glsl(layout: &Layout) -> String {
+    let mut s = format!("{}\n\nstruct CellState {{\n", GENERATED_NOTICE);
+    for f in &layout.fields {
+        s += &format!("    {} {};\n", f.ty.glsl_name(), f.name);
+    }
+    s += "};\n";
+    s += &format!(
+        "layout(std140) uniform BoardState {{\n    CellState cells[{}];\n}} boardState;\n",
+        layout.cell_count
+    );
+    s
+}
+
+fn generate_ts(layout: &Layout) -> String {
+    let mut s = format!("{}\n\n", GENERATED_NOTICE);
+    s += &format!(
+        "export const BOARD_CELL_COUNT: number = {};\n\n",
+        layout.cell_count
+    );
+    s += "export class CellStateStorable {\n";
+    s += &format!("    static readonly SIZE: number = {};\n", layout.size);
+    s += &format!(
+        "    static readonly STD140_ARRAY_STRIDE: number = {};\n",
+        layout.array_stride
+    );
+    s += "    constructor(private readonly view: DataView) {}\n";
+    for f in &layout.fields {
+        let accessor = f.ty.dataview_accessor();
+        s += &format!(
+            "\n    get {name}(): number {{\n        return this.view.get{acc}({ofs}, true);\n    }}\n",
+            name = f.name,
+            acc = accessor,
+            ofs = f.offset
+        );
+        s += &format!(
+            "    set {name}(v: number) {{\n        this.view.set{acc}({ofs}, v, true);\n    }}\n",
+            name = f.name,
+            acc = accessor,
+            ofs = f.offset
+        );
+    }
+    s += "}\n";
+    s
+}
+
+fn generate_rust(layout: &Layout) -> String {
+    let offsets: Vec<_> =
+        layout.fields.iter().map(|f| f.offset.to_string()).collect();
+    format!(
+        "#[allow(dead_code)]\nimpl CellState {{\n    pub const STD140_SIZE: usize = {};\n    pub const STD140_ARRAY_STRIDE: usize = {};\n    pub const STD140_FIELD_OFFSETS: &'static [usize] = &[{}];\n}}\n",
+        layout.size,
+        layout.array_stride,
+        offsets.join(", ")
+    )
+}
+
+/// Writes only if the contents changed, so that asset watchers and
+/// script bundlers are not triggered needlessly
+fn write_if_changed(path: impl AsRef<Path>, contents: &str) {
+    let path = path.as_ref();
+    if std::fs::read_to_string(path).map_or(false, |s| s == contents) {
+        return;
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .expect("Failed to create output directory");
+    }
+    std::fs::write(path, contents).expect("Failed to write generated file");
+}
+
+/// Rewrites a checked-in file if `update`, otherwise fails the build if it
+/// differs from `contents`
+fn check_checked_in(path: &str, contents: &str, update: bool) {
+    if update {
+        write_if_changed(path, contents);
+    } else if std::fs::read_to_string(path).ok().as_deref() != Some(contents) {
+        panic!(
+            "{} is out of date, build with {}=1 to regenerate it",
+            path, UPDATE_ENV
+        );
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", CELL_STATE_SOURCE);
+    println!("cargo:rerun-if-changed={}", GLSL_OUTPUT);
+    println!("cargo:rerun-if-changed={}", TS_OUTPUT);
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed={}", UPDATE_ENV);
+
+    let source = std::fs::read_to_string(CELL_STATE_SOURCE)
+        .expect("Failed to read cell_state.rs");
+    let layout = parse_layout(&source);
+
+    let update = std::env::var_os(UPDATE_ENV).map_or(false, |v| v == "1");
+    check_checked_in(GLSL_OUTPUT, &generate_glsl(&layout), update);
+    check_checked_in(TS_OUTPUT, &generate_ts(&layout), update);
+    let out_dir = std::env::var("OUT_DIR").expect("no OUT_DIR");
+    write_if_changed(
+        Path::new(&out_dir).join("cell_state_layout.rs"),
+        &generate_rust(&layout),
+    );
+}
@@ -0,0 +1,30 @@
+// generated by build.rs from src/cell_state.rs. DO NOT EDIT.
+
+export const BOARD_CELL_COUNT: number = 144;
+
+export class CellStateStorable {
+    static readonly SIZE: number = 12;
+    static readonly STD140_ARRAY_STRIDE: number = 16;
+    constructor(private readonly view: DataView) {}
+
+    get stateFlags(): number {
+        return this.view.getUint32(0, true);
+    }
+    set stateFlags(v: number) {
+        this.view.setUint32(0, v, true);
+    }
+
+    get flipProgress(): number {
+        return this.view.getFloat32(4, true);
+    }
+    set flipProgress(v: number) {
+        this.view.setFloat32(4, v, true);
+    }
+
+    get placeProgress(): number {
+        return this.view.getFloat32(8, true);
+    }
+    set placeProgress(v: number) {
+        this.view.setFloat32(8, v, true);
+    }
+}
@@ -1,3 +1,5 @@
+import { BOARD_CELL_COUNT, CellStateStorable } from "./generated/cellState";
+
 declare function requestNextFrame(callback: () => void): void;
 function nextFrame(): Promise<void> {
     return new Promise((resolve) => requestNextFrame(resolve));
@@ -6,9 +8,197 @@ declare function isButtonPressing(): boolean;
 declare function cursorPos(): [number, number];
 declare function setBoardStateBuffer(buffer: ArrayBuffer): void;
 declare function currentTimeMs(): number;
+/** Cells in a row of the board, chosen at startup */
+declare function boardSize(): number;
+declare function emitParticles(x: number, y: number, preset: string): void;
+declare function setTheme(name: string): void;
+
+type Easing =
+    | "linear"
+    | "easeInQuad"
+    | "easeOutQuad"
+    | "easeInOutQuad"
+    | "easeOutCubic"
+    | "easeOutQuart"
+    | "easeOutBack"
+    | "easeOutBounce";
+type AnimationOptions = {
+    duration: number;
+    delay?: number;
+    /** added to the delay for each subsequent cell */
+    stagger?: number;
+    easing?: Easing;
+};
+/** Tweens a float field of CellState; resolves when all cells finished */
+declare function animateCells(
+    cells: [number, number][],
+    field: string,
+    options: AnimationOptions & { from: number; to: number }
+): Promise<void>;
+/** Flips cells visually (flipProgress 0 to 1, easeOutQuart by default) */
+declare function animateFlip(
+    cells: [number, number][],
+    options: AnimationOptions
+): Promise<void>;
+declare function wait(ms: number): Promise<void>;
+
+type Color = [number, number, number] | [number, number, number, number];
+declare function drawRect(
+    x: number,
+    y: number,
+    w: number,
+    h: number,
+    color: Color
+): void;
+declare function drawRoundedRect(
+    x: number,
+    y: number,
+    w: number,
+    h: number,
+    radius: number,
+    color: Color
+): void;
+declare function drawCircle(
+    cx: number,
+    cy: number,
+    radius: number,
+    color: Color
+): void;
+declare function drawLine(
+    x0: number,
+    y0: number,
+    x1: number,
+    y1: number,
+    width: number,
+    color: Color
+): void;
+declare function drawImage(
+    texture: number,
+    x: number,
+    y: number,
+    w: number,
+    h: number,
+    color?: Color
+): void;
+/** Loads a PNG file and returns a texture for drawImage */
+declare function loadTexture(path: string): number;
+/** Side played by the engine (--ai), null if both sides are human */
+declare function aiColor(): "white" | "black" | null;
+/** Searches the board state buffer; resolves to null if the side must pass */
+declare function requestAiMove(
+    color: "white" | "black",
+    budgetMs: number
+): Promise<[number, number] | null>;
+/** Exact final disc differential of color with perfect play (8x8 only) */
+declare function solveEndgame(
+    color: "white" | "black"
+): Promise<{ move: [number, number] | null; score: number }>;
+type BookMove = {
+    move: [number, number];
+    /** number of games in the book playing the move */
+    weight: number;
+    /** mean final disc differential for the mover, null if unknown */
+    score: number | null;
+};
+/** Opening book candidates; color defaults to the side to move */
+declare function bookMoves(color?: "white" | "black"): BookMove[];
+type Evaluation = {
+    /** from black: final disc differential if exact, search score otherwise */
+    score: number;
+    exact: boolean;
+    depth: number;
+    /** -1 (white wins) to 1 (black wins) */
+    advantage: number;
+};
+/** Background analysis of the board state, null until available (8x8 only) */
+declare function currentEvaluation(): Evaluation | null;
+type HistoryEntry = {
+    color: "white" | "black";
+    /** null for a pass */
+    move: [number, number] | null;
+    flipped: [number, number][];
+    timeMs: number;
+};
+/**
+ * The last played move (or pass), taken back by the game and to be animated;
+ * null at the start of the game
+ */
+declare function undoMove(): HistoryEntry | null;
+/** The last undone move, played again by the game; null if there is none */
+declare function redoMove(): HistoryEntry | null;
+declare function moveHistory(): HistoryEntry[];
+/** Undo (Ctrl+Z) and redo (Ctrl+Y, Ctrl+Shift+Z) requested by keys */
+declare function takeHistoryCommand(): "undo" | "redo" | null;
+/** Writes the history as GGF for .ggf paths, as a transcript otherwise */
+declare function saveGame(path: string): void;
+/** Reads a transcript or GGF file for takeLoadedGame */
+declare function loadGame(path: string): void;
+type StartPosition = {
+    black: [number, number][];
+    white: [number, number][];
+    toMove: "white" | "black";
+};
+/**
+ * A game loaded by loadGame, --load or a dropped file, whose moves replace
+ * the history; null if none is pending
+ */
+declare function takeLoadedGame(): {
+    start: StartPosition;
+    moves: HistoryEntry[];
+} | null;
+/** Position the game starts from (--position), null on boards but 8x8 */
+declare function startPosition(): StartPosition | null;
+type GameResult = {
+    type: "end";
+    /** null for a draw */
+    winner: "white" | "black" | null;
+    black: number;
+    white: number;
+    reason: "boardFull" | "noMoves" | "resignation" | "timeout";
+};
+type GameEvent =
+    | {
+          type: "placed";
+          color: "white" | "black";
+          move: [number, number];
+          flipped: [number, number][];
+      }
+    | { type: "pass"; color: "white" | "black" }
+    | {
+          type: "turn";
+          color: "white" | "black";
+          legalMoves: [number, number][];
+      }
+    | GameResult;
+/** Events of the game, delivered at the start of frames in order */
+declare function onGameEvent(callback: (e: GameEvent) => void): void;
+/** Places a disc for the side to move; returns the flipped cells */
+declare function playMove(x: number, y: number): [number, number][];
+declare function resign(color: "white" | "black"): void;
+/** Runs this script again on a new game (also the N key) */
+declare function newGame(): void;
+type SideClock = {
+    /** of the byoyomi period once the main time ran out */
+    remainingMs: number;
+    byoyomi: boolean;
+};
+/** Clocks of both sides (--time), null for untimed games */
+declare function gameClock(): {
+    black: SideClock;
+    white: SideClock;
+    running: "white" | "black" | null;
+    /** stopped during animations (--pause-clock) */
+    paused: boolean;
+} | null;
+/** column pans the sound to a board column (overrides pan) */
+declare function playSound(
+    name: string,
+    options?: { volume?: number; pan?: number; column?: number }
+): void;
 
 type StorableClassProperties<T> = {
     readonly SIZE: number;
+    readonly STD140_ARRAY_STRIDE: number;
     new (view: DataView): T;
 };
 class StorableStd140Array<T> {
@@ -19,8 +209,7 @@ class StorableStd140Array<T> {
         private readonly cls: StorableClassProperties<T>,
         elements: number
     ) {
-        // glsl std140 layout restriction
-        this.stride = Math.trunc((cls.SIZE + 15) / 16) * 16;
+        this.stride = cls.STD140_ARRAY_STRIDE;
         this.buffer = new ArrayBuffer(this.stride * elements);
     }
 
@@ -31,27 +220,11 @@ class StorableStd140Array<T> {
     }
 }
 
-class CellStateStorable {
-    static readonly SIZE: number = 8;
-    constructor(private readonly view: DataView) {}
-
-    get stateFlags(): number {
-        return this.view.getUint32(0, true);
-    }
-    set stateFlags(v: number) {
-        this.view.setUint32(0, v, true);
-    }
-    get flipStartTime(): number {
-        return this.view.getFloat32(4, true);
-    }
-    set flipStartTime(v: number) {
-        this.view.setFloat32(4, v, true);
-    }
-}
 class CellState extends CellStateStorable {
     init() {
         this.stateFlags = 0;
-        this.flipStartTime = 0;
+        this.flipProgress = 1;
+        this.placeProgress = 1;
     }
 
     get placed(): boolean {
@@ -72,76 +245,92 @@ class CellState extends CellStateStorable {
         if (!this.placed) return;
         this.stateFlags ^= 0x01;
     }
-
-    beginFlipAnimation(at: number) {
-        this.flipStartTime = at;
-    }
 }
-const AROUND_DIRECTIONS = [
-    [-1, -1],
-    [0, -1],
-    [1, -1],
-    [-1, 0],
-    [1, 0],
-    [-1, 1],
-    [0, 1],
-    [1, 1],
-];
+const PLACE_DURATION_MS = 500;
+/** First touchdown of easeOutBounce (1 / 2.75 of the duration) */
+const PLACE_LANDING_MS = PLACE_DURATION_MS / 2.75;
+const FLIP_DURATION_MS = 250;
+/** Lifting a stone off the board on undo */
+const UNPLACE_DURATION_MS = 200;
+/** Delay between rings of flipped stones around the placed one */
+const FLIP_RING_INTERVAL_MS = 66;
+const AI_BUDGET_MS = 1000;
+/** Finished games are saved here (8x8 only) */
+const LAST_GAME_PATH = "./saves/last_game.ggf";
+/** The expected result is logged from this many empty cells (8x8 only) */
+const ENDGAME_READOUT_EMPTIES = 16;
 class BoardState {
-    // for std140 uniform layout
-    private cells = new StorableStd140Array(CellState, 8 * 8);
+    readonly size = boardSize();
+    // for std140 uniform layout, sized for the largest board
+    private cells = new StorableStd140Array(CellState, BOARD_CELL_COUNT);
     private whiteCounter = 2;
     private blackCounter = 2;
 
-    constructor() {
-        for (let y = 0; y < 8; y++) {
-            for (let x = 0; x < 8; x++) {
-                this.cells.get(x + y * 8).init();
+    /** Starts from the centred four discs if start is null */
+    constructor(start: StartPosition | null) {
+        for (let y = 0; y < this.size; y++) {
+            for (let x = 0; x < this.size; x++) {
+                this.cells.get(x + y * this.size).init();
             }
         }
-        this.cell(3, 3)!.place("black");
-        this.cell(4, 4)!.place("black");
-        this.cell(4, 3)!.place("white");
-        this.cell(3, 4)!.place("white");
+        if (start !== null) {
+            for (const [x, y] of start.black) this.cell(x, y)!.place("black");
+            for (const [x, y] of start.white) this.cell(x, y)!.place("white");
+            this.blackCounter = start.black.length;
+            this.whiteCounter = start.white.length;
+            return;
+        }
+        const c = this.size / 2;
+        this.cell(c - 1, c - 1)!.place("black");
+        this.cell(c, c)!.place("black");
+        this.cell(c, c - 1)!.place("white");
+        this.cell(c - 1, c)!.place("white");
     }
 
     cell(x: number, y: number): CellState | undefined {
-        if (0 <= x && x < 8 && 0 <= y && y < 8) {
-            return this.cells.get(x + y * 8);
+        if (0 <= x && x < this.size && 0 <= y && y < this.size) {
+            return this.cells.get(x + y * this.size);
         }
     }
 
-    /** Returns true if successfully placed the stone, escapes frame for motion */
+    /**
+     * Animates a move played by the game, flipping in rings around the
+     * placed stone. Escapes frame for motion.
+     */
     async place(
         x: number,
         y: number,
-        color: "white" | "black"
-    ): Promise<boolean> {
+        color: "white" | "black",
+        flipped: [number, number][]
+    ): Promise<void> {
         const c = this.cell(x, y);
-        if (!c) return false;
-        if (c.placed) return false;
+        if (!c || c.placed) return;
         c.place(color);
+        this.syncStateBuffer();
+        const dropped = animateCells([[x, y]], "placeProgress", {
+            from: 0,
+            to: 1,
+            duration: PLACE_DURATION_MS,
+            easing: "easeOutBounce",
+        });
+        await wait(PLACE_LANDING_MS);
+        emitParticles(x, y, "place");
+        playSound("place", { column: x });
         if (color === "white") {
             this.whiteCounter++;
         } else {
             this.blackCounter++;
         }
-        const flipDirections: [number, number, number][] =
-            AROUND_DIRECTIONS.flatMap(([dx, dy]) => {
-                const flipCount = this.findFlipCount(x, y, dx, dy, color);
-                if (!flipCount) return [];
-                return [[dx, dy, flipCount]];
-            });
-        const flipDirectionMax = flipDirections.reduce(
-            (a, [, , c]) => Math.max(a, c),
-            0
-        );
-        for (let mag = 1; mag <= flipDirectionMax; mag++) {
-            for (const [dx, dy, max] of flipDirections) {
-                if (max < mag) continue;
-                const c = this.cell(x + dx * mag, y + dy * mag)!;
-                c.flip();
-                c.beginFlipAnimation(currentTimeMs());
+        const distance = ([fx, fy]: [number, number]) =>
+            Math.max(Math.abs(fx - x), Math.abs(fy - y));
+        const ringMax = flipped.reduce((a, c) => Math.max(a, distance(c)), 0);
+        const animations: Promise<void>[] = [dropped];
+        for (let mag = 1; mag <= ringMax; mag++) {
+            const ring = flipped.filter((c) => distance(c) === mag);
+            for (const [fx, fy] of ring) {
+                this.cell(fx, fy)!.flip();
+                emitParticles(fx, fy, "flip");
+                playSound("flip", { column: fx, volume: 0.6 });
                 if (color === "white") {
                     this.whiteCounter++;
                     this.blackCounter--;
@@ -151,67 +340,87 @@ class BoardState {
                 }
             }
             this.syncStateBuffer();
-            for (let i = 0; i < 4; i++) await nextFrame();
+            animations.push(animateFlip(ring, { duration: FLIP_DURATION_MS }));
+            if (mag < ringMax) await wait(FLIP_RING_INTERVAL_MS);
         }
         this.syncStateBuffer();
-        return true;
+        await Promise.all(animations);
+    }
+
+    /** Replays a recorded move without animations */
+    restore(entry: HistoryEntry) {
+        if (!entry.move) return;
+        const [x, y] = entry.move;
+        this.cell(x, y)!.place(entry.color);
+        for (const [fx, fy] of entry.flipped) {
+            this.cell(fx, fy)!.flip();
+        }
+        if (entry.color === "white") {
+            this.whiteCounter += entry.flipped.length + 1;
+            this.blackCounter -= entry.flipped.length;
+        } else {
+            this.blackCounter += entry.flipped.length + 1;
+            this.whiteCounter -= entry.flipped.length;
+        }
+    }
+
+    /** Takes back a stone placed by color, flipping the discs back */
+    async unplace(
+        x: number,
+        y: number,
+        flipped: [number, number][],
+        color: "white" | "black"
+    ): Promise<void> {
+        const c = this.cell(x, y);
+        if (!c || !c.placed) return;
+        for (const [fx, fy] of flipped) {
+            this.cell(fx, fy)!.flip();
+        }
+        if (color === "white") {
+            this.whiteCounter -= flipped.length + 1;
+            this.blackCounter += flipped.length;
+        } else {
+            this.blackCounter -= flipped.length + 1;
+            this.whiteCounter += flipped.length;
+        }
+        this.syncStateBuffer();
+        playSound("flip", { column: x, volume: 0.6 });
+        const flips = animateFlip(flipped, { duration: FLIP_DURATION_MS });
+        await animateCells([[x, y]], "placeProgress", {
+            from: 1,
+            to: 0,
+            duration: UNPLACE_DURATION_MS,
+            easing: "easeInQuad",
+        });
+        c.init();
+        this.syncStateBuffer();
+        await flips;
     }
 
     syncStateBuffer() {
         setBoardStateBuffer(this.cells.buffer);
     }
 
-    get hasGameFinished(): boolean {
-        return this.blackCounter + this.whiteCounter >= 64;
+    get blackCount(): number {
+        return this.blackCounter;
     }
-
-    /** null = draw */
-    get winSide(): "white" | "black" | null {
-        if (this.blackCounter == this.whiteCounter) return null;
-        return this.blackCounter > this.whiteCounter ? "black" : "white";
+    get whiteCount(): number {
+        return this.whiteCounter;
+    }
+    get emptyCount(): number {
+        return this.size * this.size - this.blackCounter - this.whiteCounter;
     }
     get scoreboardText(): string {
         return `white ${this.whiteCounter} black ${this.blackCounter}`;
     }
 
-    findLegalPlacePositions(color: "white" | "black"): [number, number][] {
-        let positions: [number, number][] = [];
-        for (let y = 0; y < 8; y++) {
-            for (let x = 0; x < 8; x++) {
-                if (this.cell(x, y)!.placed) continue;
-                const flipCounts = AROUND_DIRECTIONS.map(([dx, dy]) =>
-                    this.findFlipCount(x, y, dx, dy, color)
-                ).filter(Boolean);
-                if (flipCounts.length > 0) positions.push([x, y]);
-            }
-        }
-
-        return positions;
-    }
-
-    private findFlipCount(
-        x: number,
-        y: number,
-        dx: number,
-        dy: number,
-        color: "white" | "black"
-    ): number | undefined {
-        const c2 = this.cell(x + dx, y + dy);
-        if (!c2 || !c2.placed || c2.color === color) return;
-        let mag = 2;
-        while (true) {
-            const c3 = this.cell(x + dx * mag, y + dy * mag);
-            if (!c3 || !c3.placed) return;
-            if (c3.color === color) return mag - 1;
-            mag += 1;
-        }
-    }
-
+    /** Logs the board as a grid readable by --position and show-position */
     dump() {
         let str = this.scoreboardText;
-        for (let y = 0; y < 8; y++) {
+        // from the last row, as in the standard orientation
+        for (let y = this.size - 1; y >= 0; y--) {
             str += "\n";
-            for (let x = 0; x < 8; x++) {
+            for (let x = 0; x < this.size; x++) {
                 const c = this.cell(x, y)!;
                 if (!c.placed) {
                     str += "_";
@@ -238,39 +447,143 @@ class EdgeTrigger<T> {
     }
 }
 
+/** Lit segments a to g (top, clockwise, then middle) of each digit */
+const SEVEN_SEGMENT_DIGITS = [
+    0x3f, 0x06, 0x5b, 0x4f, 0x66, 0x6d, 0x7d, 0x07, 0x7f, 0x6f,
+];
+function drawDigit(
+    x: number,
+    y: number,
+    h: number,
+    d: number,
+    color: Color
+): void {
+    const w = h * 0.5;
+    const t = h * 0.12;
+    const half = (h - t) / 2;
+    // [x, y, w, h] of segments a to g
+    const segments: [number, number, number, number][] = [
+        [x + t, y, w - t * 2, t],
+        [x + w - t, y + t, t, half - t],
+        [x + w - t, y + half + t, t, half - t],
+        [x + t, y + h - t, w - t * 2, t],
+        [x, y + half + t, t, half - t],
+        [x, y + t, t, half - t],
+        [x + t, y + half, w - t * 2, t],
+    ];
+    segments.forEach(([sx, sy, sw, sh], n) => {
+        if (SEVEN_SEGMENT_DIGITS[d] & (1 << n)) {
+            drawRect(sx, sy, sw, sh, color);
+        }
+    });
+}
+/** Draws a non-negative integer in seven segments; returns the width */
+function drawNumber(
+    x: number,
+    y: number,
+    h: number,
+    n: number,
+    color: Color
+): number {
+    const advance = h * 0.5 + h * 0.2;
+    let cx = x;
+    for (const c of `${Math.trunc(n)}`) {
+        drawDigit(cx, y, h, Number(c), color);
+        cx += advance;
+    }
+    return cx - h * 0.2 - x;
+}
+/** Draws M:SS (minutes up to 99) in seven segments; returns the width */
+function drawClockTime(
+    x: number,
+    y: number,
+    h: number,
+    ms: number,
+    color: Color
+): number {
+    const total = Math.min(Math.ceil(ms / 1000), 99 * 60 + 59);
+    const minutes = Math.trunc(total / 60);
+    const seconds = total % 60;
+    const advance = h * 0.5 + h * 0.2;
+    let cx = x + drawNumber(x, y, h, minutes, color) + h * 0.2;
+    const dot = h * 0.12;
+    drawRect(cx, y + h * 0.25, dot, dot, color);
+    drawRect(cx, y + h * 0.65, dot, dot, color);
+    cx += dot + h * 0.2;
+    drawDigit(cx, y, h, Math.trunc(seconds / 10), color);
+    cx += advance;
+    drawDigit(cx, y, h, seconds % 10, color);
+    return cx + h * 0.5 - x;
+}
+
 class BoardControl {
-    private state = new BoardState();
+    private readonly start = startPosition();
+    private state = new BoardState(this.start);
     private buttonPressEdge = new EdgeTrigger(false);
-    private currentPhase: "black" | "white" = "white";
+    private currentPhase: "black" | "white" = "black";
     private legalPlacePositions: [number, number][] = [];
+    private result: GameResult | null = null;
+    private readonly aiColor = aiColor();
+
+    constructor() {
+        onGameEvent((e) => this.onGameEvent(e));
+    }
 
     async run(): Promise<void> {
         const aroundMargin = 480 * (1.0 - 0.78) * 0.5;
-        const boardSize = 480 - aroundMargin * 2;
-        const cellSize = boardSize / 8;
+        const boardExtent = 480 - aroundMargin * 2;
+        const cellSize = boardExtent / this.state.size;
         // to initialize internal states
         this.state.syncStateBuffer();
-        this.flipTurn();
+        // delivers the first turn
+        await nextFrame();
 
-        while (!this.state.hasGameFinished) {
+        while (this.result === null) {
+            // the game reports the new turn at the next frame
+            const loaded = takeLoadedGame();
+            if (loaded !== null) {
+                this.restoreGame(loaded.start, loaded.moves);
+                await nextFrame();
+                continue;
+            }
+            const command = takeHistoryCommand();
+            if (command !== null) {
+                await this.applyHistoryCommand(command);
+                await nextFrame();
+                continue;
+            }
+            if (this.currentPhase === this.aiColor) {
+                // leaves time for the rest of the game
+                const clock = gameClock();
+                const budget =
+                    clock === null
+                        ? AI_BUDGET_MS
+                        : Math.min(
+                              AI_BUDGET_MS,
+                              clock[this.currentPhase].remainingMs / 10
+                          );
+                const move = await requestAiMove(this.currentPhase, budget);
+                if (move) await this.placeAndFlipTurn(move[0], move[1]);
+                continue;
+            }
             if (
                 this.buttonPressEdge.update(isButtonPressing()) &&
                 this.buttonPressEdge.current
             ) {
                 const [cx, cy] = cursorPos();
                 const [bx, by] = [cx - aroundMargin, cy - aroundMargin];
-                if (0 <= bx && bx < boardSize && 0 <= by && by < boardSize) {
+                if (
+                    0 <= bx &&
+                    bx < boardExtent &&
+                    0 <= by &&
+                    by < boardExtent
+                ) {
                     const [cellX, cellY] = [
                         Math.trunc(bx / cellSize),
                         Math.trunc(by / cellSize),
                     ];
                     if (this.isLegalPlacePosition(cellX, cellY)) {
-                        await this.state.place(cellX, cellY, this.currentPhase);
-                        if (!this.state.hasGameFinished) {
-                            do {
-                                this.flipTurn();
-                            } while (this.legalPlacePositions.length <= 0);
-                        }
+                        await this.placeAndFlipTurn(cellX, cellY);
                     }
                 }
             }
@@ -278,12 +591,239 @@ class BoardControl {
             await nextFrame();
         }
         console.log("Game finished");
-        const ws = this.state.winSide;
+        const r = this.result;
         console.log(
-            `${ws === null ? "draw" : ws + " win"} (${
-                this.state.scoreboardText
-            })`
+            `${r.winner === null ? "draw" : r.winner + " win"} (white ${
+                r.white
+            } black ${r.black}, ${r.reason})`
         );
+        if (this.state.size === 8) {
+            try {
+                saveGame(LAST_GAME_PATH);
+            } catch (e) {
+                console.log(`${e}`);
+            }
+        }
+        await this.showResult(r);
+    }
+
+    /** Shows the final counts over the board; a click starts a new game */
+    private async showResult(result: GameResult): Promise<void> {
+        while (true) {
+            drawRect(0, 0, 480, 480, [0, 0, 0, 0.4]);
+            drawRoundedRect(120, 200, 240, 80, 12, [0, 0, 0, 0.6]);
+            const discs: ["black" | "white", number][] = [
+                ["black", 156],
+                ["white", 324],
+            ];
+            for (const [side, x] of discs) {
+                if (result.winner === side) {
+                    drawCircle(x, 240, 20, [1, 0.8, 0.2]);
+                }
+                const disc: Color = side === "white" ? [1, 1, 1] : [0, 0, 0];
+                drawCircle(x, 240, 16, disc);
+            }
+            const digitHeight = 28;
+            const digitY = 240 - digitHeight / 2;
+            drawNumber(184, digitY, digitHeight, result.black, [1, 1, 1]);
+            // right-aligned to the white disc
+            const advance = digitHeight * 0.7;
+            const whiteWidth =
+                `${result.white}`.length * advance - digitHeight * 0.2;
+            drawNumber(
+                296 - whiteWidth,
+                digitY,
+                digitHeight,
+                result.white,
+                [1, 1, 1]
+            );
+
+            if (
+                this.buttonPressEdge.update(isButtonPressing()) &&
+                this.buttonPressEdge.current
+            ) {
+                newGame();
+                return;
+            }
+            await nextFrame();
+        }
+    }
+
+    /**
+     * Draws the turn indicator, score bar, evaluation bar, clocks and legal
+     * move hints every frame
+     */
+    async runHud(): Promise<void> {
+        const aroundMargin = 480 * (1.0 - 0.78) * 0.5;
+        const cellSize = (480 - aroundMargin * 2) / this.state.size;
+        while (true) {
+            const turnColor: Color =
+                this.currentPhase === "white" ? [1, 1, 1] : [0, 0, 0];
+            drawRoundedRect(8, 8, 480 - 16, aroundMargin - 16, 8, [
+                0, 0, 0, 0.35,
+            ]);
+            drawCircle(8 + 18, aroundMargin * 0.5, 10, turnColor);
+
+            const barX = 8 + 40;
+            const barWidth = 480 - 16 - 52;
+            const total = this.state.blackCount + this.state.whiteCount;
+            const blackWidth = (barWidth * this.state.blackCount) / total;
+            drawRect(barX, aroundMargin * 0.5 - 4, blackWidth, 8, [0, 0, 0]);
+            drawRect(
+                barX + blackWidth,
+                aroundMargin * 0.5 - 4,
+                barWidth - blackWidth,
+                8,
+                [1, 1, 1]
+            );
+
+            const evaluation = currentEvaluation();
+            if (evaluation !== null) {
+                const evalX = 480 - aroundMargin + 12;
+                const evalHeight = 480 - aroundMargin * 2;
+                const blackHeight =
+                    (evalHeight * (evaluation.advantage + 1)) / 2;
+                drawRect(
+                    evalX,
+                    aroundMargin,
+                    10,
+                    evalHeight - blackHeight,
+                    [1, 1, 1]
+                );
+                drawRect(
+                    evalX,
+                    480 - aroundMargin - blackHeight,
+                    10,
+                    blackHeight,
+                    [0, 0, 0]
+                );
+                if (evaluation.exact) {
+                    // marks a solved position at the even line
+                    drawRect(evalX - 2, 240 - 1, 14, 2, [0.9, 0.2, 0.2]);
+                }
+            }
+
+            const clock = gameClock();
+            if (clock !== null) {
+                const panelY = 480 - aroundMargin + 8;
+                const panelHeight = aroundMargin - 16;
+                drawRoundedRect(8, panelY, 480 - 16, panelHeight, 8, [
+                    0, 0, 0, 0.35,
+                ]);
+                const digitHeight = panelHeight * 0.55;
+                const digitY = panelY + (panelHeight - digitHeight) / 2;
+                const sides: ["black" | "white", number][] = [
+                    ["black", 8 + 18],
+                    ["white", 240 + 18],
+                ];
+                for (const [side, x] of sides) {
+                    const c = clock[side];
+                    const disc: Color =
+                        side === "white" ? [1, 1, 1] : [0, 0, 0];
+                    drawCircle(x, panelY + panelHeight / 2, 10, disc);
+                    // dimmed while waiting, orange in byoyomi
+                    const alpha =
+                        clock.running === side && !clock.paused ? 1 : 0.45;
+                    const digitColor: Color = c.byoyomi
+                        ? [1, 0.6, 0.2, alpha]
+                        : [1, 1, 1, alpha];
+                    drawClockTime(
+                        x + 22,
+                        digitY,
+                        digitHeight,
+                        c.remainingMs,
+                        digitColor
+                    );
+                }
+            }
+
+            for (const [x, y] of this.legalPlacePositions) {
+                drawCircle(
+                    aroundMargin + (x + 0.5) * cellSize,
+                    aroundMargin + (y + 0.5) * cellSize,
+                    cellSize * 0.12,
+                    [...turnColor, 0.4] as Color
+                );
+            }
+
+            await nextFrame();
+        }
+    }
+
+    private async placeAndFlipTurn(x: number, y: number) {
+        // a flag may have fallen while the move was being chosen
+        if (this.result !== null) return;
+        const color = this.currentPhase;
+        const flipped = playMove(x, y);
+        await this.state.place(x, y, color, flipped);
+        this.state.dump();
+        this.logEndgameResult();
+    }
+
+    /** Replaces the board with a loaded game, continuing from its end */
+    private restoreGame(start: StartPosition, moves: HistoryEntry[]) {
+        this.state = new BoardState(start);
+        for (const e of moves) {
+            this.state.restore(e);
+        }
+        this.state.syncStateBuffer();
+        this.state.dump();
+    }
+
+    /**
+     * Undoes or redoes moves until a human player is to move, skipping moves
+     * of the engine and passes
+     */
+    private async applyHistoryCommand(command: "undo" | "redo") {
+        while (true) {
+            const entry = command === "undo" ? undoMove() : redoMove();
+            if (entry === null) return;
+            // passes follow from the rules
+            if (!entry.move) continue;
+            const [x, y] = entry.move;
+            if (command === "undo") {
+                await this.state.unplace(x, y, entry.flipped, entry.color);
+                if (entry.color !== this.aiColor) return;
+            } else {
+                await this.state.place(x, y, entry.color, entry.flipped);
+                if (this.aiColor === null || entry.color === this.aiColor) {
+                    return;
+                }
+            }
+        }
+    }
+
+    private onGameEvent(e: GameEvent) {
+        switch (e.type) {
+            case "turn":
+                this.currentPhase = e.color;
+                this.legalPlacePositions = e.legalMoves;
+                console.log(`${e.color} phase`);
+                break;
+            case "pass":
+                console.log(`${e.color} passes`);
+                break;
+            case "end":
+                this.result = e;
+                this.legalPlacePositions = [];
+                break;
+        }
+    }
+
+    private logEndgameResult() {
+        if (this.state.size !== 8) return;
+        if (this.state.emptyCount > ENDGAME_READOUT_EMPTIES) return;
+        const color = this.currentPhase;
+        solveEndgame(color).then(({ score }) => {
+            const opponent = color === "white" ? "black" : "white";
+            const result =
+                score == 0
+                    ? "draw"
+                    : score > 0
+                    ? `${color} wins by ${score}`
+                    : `${opponent} wins by ${-score}`;
+            console.log(`perfect play: ${result}`);
+        });
     }
 
     private isLegalPlacePosition(x: number, y: number): boolean {
@@ -292,16 +832,7 @@ class BoardControl {
             undefined
         );
     }
-
-    private flipTurn() {
-        this.currentPhase = this.currentPhase === "white" ? "black" : "white";
-        this.state.dump();
-        console.log(`${this.currentPhase} phase`);
-        this.legalPlacePositions = this.state.findLegalPlacePositions(
-            this.currentPhase
-        );
-    }
 }
 
 const board = new BoardControl();
-Promise.all([board.run()]);
+Promise.all([board.run(), board.runHud()]);
@@ -0,0 +1,142 @@
+//! Continuous evaluation of the current position on a background thread
+//!
+//! The position is searched with doubling time budgets until the next one
+//! arrives, publishing the evaluation after each search. Near the end of the
+//! game it is solved exactly instead.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::bitboard::{Color, Position};
+use crate::endgame;
+use crate::search::{search, WIN_SCORE};
+
+/// Positions with this many empties or less are solved exactly
+const EXACT_EMPTIES: u32 = 16;
+const FIRST_BUDGET: Duration = Duration::from_millis(50);
+/// Analysis of a position stops after a search with this budget
+const MAX_BUDGET: Duration = Duration::from_millis(3200);
+/// Search score giving about 76% on the bar (tanh(1))
+const ADVANTAGE_SCALE: f32 = 300.0;
+
+#[derive(Clone, Copy)]
+pub struct Evaluation {
+    /// From black: the final disc differential if exact, the search score
+    /// otherwise
+    pub score: i32,
+    pub exact: bool,
+    /// Searched depth
+    pub depth: u32,
+}
+impl Evaluation {
+    /// How far black is ahead, from -1 (white wins) to 1 (black wins)
+    pub fn advantage(&self) -> f32 {
+        if self.exact {
+            // any win is more than half of the bar
+            return match self.score {
+                0 => 0.0,
+                s => s.signum() as f32 * (0.5 + s.abs() as f32 / 128.0),
+            };
+        }
+        if self.score.abs() >= WIN_SCORE {
+            return self.score.signum() as f32;
+        }
+        (self.score as f32 / ADVANTAGE_SCALE).tanh()
+    }
+}
+
+type Published = Arc<Mutex<Option<(Position, Evaluation)>>>;
+
+/// Analyzes the position until done or a newer one arrives, returned if so
+fn analyze(
+    pos: Position,
+    to_move: Color,
+    positions: &Receiver<(Position, Color)>,
+    published: &Published,
+) -> Option<(Position, Color)> {
+    let from_black = |score: i32| match to_move {
+        Color::Black => score,
+        Color::White => -score,
+    };
+    let publish = |evaluation| {
+        *published.lock().expect("poisoned") = Some((pos, evaluation));
+    };
+
+    let empties = pos.empties().count_ones();
+    // the game passes for a side without moves, so none means it is over
+    if empties <= EXACT_EMPTIES || pos.legal_moves() == 0 {
+        let s = endgame::solve(&pos);
+        publish(Evaluation {
+            score: from_black(s.score),
+            exact: true,
+            depth: empties,
+        });
+        return None;
+    }
+
+    let mut budget = FIRST_BUDGET;
+    while budget <= MAX_BUDGET {
+        let r = search(&pos, budget);
+        publish(Evaluation {
+            score: from_black(r.score),
+            exact: false,
+            depth: r.depth,
+        });
+        if let Some(next) = positions.try_iter().last() {
+            return Some(next);
+        }
+        budget *= 2;
+    }
+    None
+}
+
+pub struct Analyzer {
+    positions: Sender<(Position, Color)>,
+    published: Published,
+    current: Option<Position>,
+}
+impl Analyzer {
+    pub fn new() -> Self {
+        let (positions, position_rx) = channel();
+        let published = Published::default();
+        let thread_published = published.clone();
+        std::thread::Builder::new()
+            .name("analysis".into())
+            .spawn(move || {
+                // ends when the analyzer is dropped
+                let mut next = position_rx.recv().ok();
+                while let Some((pos, to_move)) = next {
+                    next =
+                        analyze(pos, to_move, &position_rx, &thread_published)
+                            .or_else(|| position_rx.recv().ok());
+                }
+            })
+            .expect("Failed to spawn analysis thread");
+
+        Analyzer {
+            positions,
+            published,
+            current: None,
+        }
+    }
+
+    /// Starts analyzing the position unless it is already
+    pub fn set_position(&mut self, pos: Position, to_move: Color) {
+        if self.current == Some(pos) {
+            return;
+        }
+        self.current = Some(pos);
+        self.positions
+            .send((pos, to_move))
+            .expect("analysis thread has terminated");
+    }
+
+    /// Latest evaluation of the current position
+    pub fn evaluation(&self) -> Option<Evaluation> {
+        match *self.published.lock().expect("poisoned") {
+            Some((pos, e)) if Some(pos) == self.current => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Detects modifications of files in an asset directory by polling mtimes
+pub struct AssetWatcher {
+    dir: PathBuf,
+    modified_times: HashMap<PathBuf, SystemTime>,
+    last_checked: Instant,
+}
+impl AssetWatcher {
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref().to_owned();
+        let modified_times = Self::scan(&dir);
+
+        AssetWatcher {
+            dir,
+            modified_times,
+            last_checked: Instant::now(),
+        }
+    }
+
+    /// Returns true if any file has been added, removed or modified
+    /// since the last call
+    pub fn poll(&mut self) -> bool {
+        if self.last_checked.elapsed() < Self::POLL_INTERVAL {
+            return false;
+        }
+        self.last_checked = Instant::now();
+
+        let modified_times = Self::scan(&self.dir);
+        if modified_times == self.modified_times {
+            return false;
+        }
+        self.modified_times = modified_times;
+        true
+    }
+
+    fn scan(dir: &Path) -> HashMap<PathBuf, SystemTime> {
+        let mut modified_times = HashMap::new();
+        Self::scan_into(dir, &mut modified_times);
+        modified_times
+    }
+    fn scan_into(
+        dir: &Path,
+        modified_times: &mut HashMap<PathBuf, SystemTime>,
+    ) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(e) => e,
+            Err(e) => {
+                log::warn!("Failed to watch {}: {}", dir.display(), e);
+                return;
+            }
+        };
+
+        for e in entries.filter_map(Result::ok) {
+            let md = match e.metadata() {
+                Ok(md) => md,
+                Err(_) => continue,
+            };
+            if md.is_dir() {
+                Self::scan_into(&e.path(), modified_times);
+            } else if let Ok(modified) = md.modified() {
+                modified_times.insert(e.path(), modified);
+            }
+        }
+    }
+}
@@ -0,0 +1,582 @@
+//! Sound effects: WAV/OGG decoding, a software mixer and output backends
+//!
+//! Sounds are decoded once into interleaved stereo f32 at the output sample
+//! rate. The mixer is shared with the backend: pull-based devices mix from
+//! their own thread, the others are pumped from the main loop.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const SOUNDS_DIR: &'static str = "./assets/sounds";
+
+/// Decoded sound (interleaved stereo)
+pub struct Sound {
+    samples: Vec<f32>,
+}
+impl Sound {
+    fn frames(&self) -> usize {
+        self.samples.len() / 2
+    }
+
+    /// Converts interleaved samples to stereo at `rate` (linear resampling)
+    fn from_interleaved(
+        samples: &[f32],
+        channels: usize,
+        source_rate: u32,
+        rate: u32,
+    ) -> Self {
+        let source_frames = samples.len() / channels;
+        let stereo_at = |n: usize| {
+            let f = &samples[n * channels..(n + 1) * channels];
+            if channels == 1 {
+                (f[0], f[0])
+            } else {
+                (f[0], f[1])
+            }
+        };
+        if source_frames == 0 {
+            return Sound {
+                samples: Vec::new(),
+            };
+        }
+
+        let step = source_rate as f64 / rate as f64;
+        // in integers, a float quotient may fall just short of a whole frame
+        let frames =
+            (source_frames as u64 * rate as u64 / source_rate as u64) as usize;
+        let mut out = Vec::with_capacity(frames * 2);
+        for n in 0..frames {
+            let pos = n as f64 * step;
+            let i = pos as usize;
+            let t = (pos - i as f64) as f32;
+            let (l0, r0) = stereo_at(i);
+            let (l1, r1) = stereo_at((i + 1).min(source_frames - 1));
+            out.push(l0 + (l1 - l0) * t);
+            out.push(r0 + (r1 - r0) * t);
+        }
+
+        Sound { samples: out }
+    }
+
+    pub fn load(path: &Path, rate: u32) -> Result<Self, String> {
+        let err = |e: &dyn std::fmt::Display| {
+            format!("Failed to load sound {}: {}", path.display(), e)
+        };
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("wav") => {
+                let mut reader =
+                    hound::WavReader::open(path).map_err(|e| err(&e))?;
+                let spec = reader.spec();
+                let samples = match spec.sample_format {
+                    hound::SampleFormat::Float => {
+                        reader.samples::<f32>().collect::<Result<Vec<_>, _>>()
+                    }
+                    hound::SampleFormat::Int => {
+                        let scale =
+                            1.0 / (1i64 << (spec.bits_per_sample - 1)) as f32;
+                        reader
+                            .samples::<i32>()
+                            .map(|s| s.map(|s| s as f32 * scale))
+                            .collect()
+                    }
+                }
+                .map_err(|e| err(&e))?;
+                Ok(Self::from_interleaved(
+                    &samples,
+                    spec.channels as _,
+                    spec.sample_rate,
+                    rate,
+                ))
+            }
+            Some("ogg") => {
+                let file = std::fs::File::open(path).map_err(|e| err(&e))?;
+                let mut reader = lewton::inside_ogg::OggStreamReader::new(file)
+                    .map_err(|e| err(&e))?;
+                let mut samples = Vec::new();
+                while let Some(packet) =
+                    reader.read_dec_packet_itl().map_err(|e| err(&e))?
+                {
+                    samples.extend(packet.iter().map(|&s| s as f32 / 32768.0));
+                }
+                Ok(Self::from_interleaved(
+                    &samples,
+                    reader.ident_hdr.audio_channels as _,
+                    reader.ident_hdr.audio_sample_rate,
+                    rate,
+                ))
+            }
+            _ => Err(err(&"unsupported format")),
+        }
+    }
+}
+
+/// Loads every WAV/OGG file in `dir`, named by the file stem
+pub fn load_sounds(dir: &str, rate: u32) -> HashMap<String, Arc<Sound>> {
+    let mut sounds = HashMap::new();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(e) => {
+            log::warn!("Failed to read sounds directory {}: {}", dir, e);
+            return sounds;
+        }
+    };
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        let name = match path.file_stem() {
+            Some(s) => s.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        match Sound::load(&path, rate) {
+            Ok(s) => {
+                sounds.insert(name, Arc::new(s));
+            }
+            Err(e) => log::warn!("{}", e),
+        }
+    }
+
+    sounds
+}
+
+pub struct PlayOptions {
+    pub volume: f32,
+    /// -1.0 (left) ..= 1.0 (right)
+    pub pan: f32,
+}
+impl Default for PlayOptions {
+    fn default() -> Self {
+        PlayOptions {
+            volume: 1.0,
+            pan: 0.0,
+        }
+    }
+}
+impl PlayOptions {
+    /// Pan for a board column (0..board_size), not fully to the sides
+    pub fn column_pan(column: f64, board_size: usize) -> f32 {
+        let center = (board_size as f32 - 1.0) * 0.5;
+        ((column as f32 - center) / center).max(-1.0).min(1.0) * 0.7
+    }
+}
+
+struct Voice {
+    sound: Arc<Sound>,
+    position: usize,
+    gain: (f32, f32),
+}
+
+/// Sums playing voices into interleaved stereo
+pub struct Mixer {
+    voices: Vec<Voice>,
+    pub master_volume: f32,
+}
+impl Mixer {
+    pub fn new() -> Self {
+        Mixer {
+            voices: Vec::new(),
+            master_volume: 0.8,
+        }
+    }
+
+    pub fn play(&mut self, sound: Arc<Sound>, options: &PlayOptions) {
+        // equal power panning
+        let angle = (options.pan.max(-1.0).min(1.0) + 1.0)
+            * std::f32::consts::FRAC_PI_4;
+        self.voices.push(Voice {
+            sound,
+            position: 0,
+            gain: (angle.cos() * options.volume, angle.sin() * options.volume),
+        });
+    }
+
+    /// Overwrites `out` (interleaved stereo) with the next frames
+    pub fn mix(&mut self, out: &mut [f32]) {
+        for s in out.iter_mut() {
+            *s = 0.0;
+        }
+        let frames = out.len() / 2;
+        for v in &mut self.voices {
+            let n = frames.min(v.sound.frames() - v.position);
+            let src = &v.sound.samples[v.position * 2..(v.position + n) * 2];
+            for (o, s) in out.chunks_mut(2).zip(src.chunks(2)) {
+                o[0] += s[0] * v.gain.0;
+                o[1] += s[1] * v.gain.1;
+            }
+            v.position += n;
+        }
+        self.voices.retain(|v| v.position < v.sound.frames());
+
+        for s in out.iter_mut() {
+            *s = (*s * self.master_volume).max(-1.0).min(1.0);
+        }
+    }
+}
+
+/// Destination of mixed samples
+pub trait AudioOutput {
+    fn sample_rate(&self) -> u32;
+    /// Called every frame from the main loop with the time since start.
+    /// Backends which pull samples by themselves do nothing.
+    fn pump(&mut self, mixer: &Mutex<Mixer>, elapsed: Duration);
+}
+
+/// Mixes in real time and writes the samples to `out`
+fn pump_realtime(
+    mixer: &Mutex<Mixer>,
+    rate: u32,
+    written_frames: &mut u64,
+    elapsed: Duration,
+    mut out: impl FnMut(&[f32]),
+) {
+    let target = (elapsed.as_secs_f64() * rate as f64) as u64;
+    if target <= *written_frames {
+        return;
+    }
+    let mut buf = vec![0.0; (target - *written_frames) as usize * 2];
+    mixer.lock().expect("mixer poisoned").mix(&mut buf);
+    out(&buf);
+    *written_frames = target;
+}
+
+/// Discards mixed samples (for machines without sound devices)
+pub struct NullOutput {
+    written_frames: u64,
+}
+impl NullOutput {
+    pub const SAMPLE_RATE: u32 = 44100;
+
+    pub fn new() -> Self {
+        NullOutput { written_frames: 0 }
+    }
+}
+impl AudioOutput for NullOutput {
+    fn sample_rate(&self) -> u32 {
+        Self::SAMPLE_RATE
+    }
+    fn pump(&mut self, mixer: &Mutex<Mixer>, elapsed: Duration) {
+        pump_realtime(
+            mixer,
+            Self::SAMPLE_RATE,
+            &mut self.written_frames,
+            elapsed,
+            |_| (),
+        );
+    }
+}
+
+/// Records mixed samples to a WAV file (32-bit float stereo)
+pub struct WavFileOutput {
+    writer: Option<hound::WavWriter<std::io::BufWriter<std::fs::File>>>,
+    written_frames: u64,
+}
+impl WavFileOutput {
+    pub const SAMPLE_RATE: u32 = 44100;
+
+    pub fn create(path: &str) -> Result<Self, String> {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: Self::SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let writer = hound::WavWriter::create(path, spec).map_err(|e| {
+            format!("Failed to create audio output {}: {}", path, e)
+        })?;
+
+        Ok(WavFileOutput {
+            writer: Some(writer),
+            written_frames: 0,
+        })
+    }
+}
+impl AudioOutput for WavFileOutput {
+    fn sample_rate(&self) -> u32 {
+        Self::SAMPLE_RATE
+    }
+    fn pump(&mut self, mixer: &Mutex<Mixer>, elapsed: Duration) {
+        let mut writer = self.writer.as_mut();
+        let mut error = None;
+        // keeps mixing after a failure so that voices still finish
+        pump_realtime(
+            mixer,
+            Self::SAMPLE_RATE,
+            &mut self.written_frames,
+            elapsed,
+            |buf| {
+                if let Some(w) = writer.as_mut() {
+                    for &s in buf {
+                        if let Err(e) = w.write_sample(s) {
+                            error = Some(e);
+                            return;
+                        }
+                    }
+                }
+            },
+        );
+        if let Some(e) = error {
+            log::warn!(
+                "Failed to write audio output, stopped recording: {}",
+                e
+            );
+            // dropping the writer tries to finalize what was written
+            self.writer = None;
+        }
+    }
+}
+impl Drop for WavFileOutput {
+    fn drop(&mut self) {
+        // writes the header with the final length
+        if let Some(w) = self.writer.take() {
+            if let Err(e) = w.finalize() {
+                log::error!("Failed to finalize audio output: {}", e);
+            }
+        }
+    }
+}
+
+/// Default output device through cpal (mixes on the device thread)
+#[cfg(feature = "cpal")]
+pub struct DeviceOutput {
+    _stream: cpal::Stream,
+    sample_rate: u32,
+}
+#[cfg(feature = "cpal")]
+impl DeviceOutput {
+    pub fn open(mixer: Arc<Mutex<Mixer>>) -> Result<Self, String> {
+        use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("no audio output device")?;
+        let config =
+            device.default_output_config().map_err(|e| e.to_string())?;
+        if config.sample_format() != cpal::SampleFormat::F32 {
+            return Err(format!(
+                "unsupported sample format: {:?}",
+                config.sample_format()
+            ));
+        }
+        let config: cpal::StreamConfig = config.into();
+        let channels = config.channels as usize;
+        let mut stereo = Vec::new();
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |data: &mut [f32], _| {
+                    let frames = data.len() / channels;
+                    stereo.resize(frames * 2, 0.0);
+                    mixer.lock().expect("mixer poisoned").mix(&mut stereo);
+                    for (o, s) in
+                        data.chunks_mut(channels).zip(stereo.chunks(2))
+                    {
+                        if channels == 1 {
+                            o[0] = (s[0] + s[1]) * 0.5;
+                            continue;
+                        }
+                        // extra channels (surround) stay silent
+                        for (c, v) in o.iter_mut().enumerate() {
+                            *v = s.get(c).copied().unwrap_or(0.0);
+                        }
+                    }
+                },
+                |e| log::error!("Audio stream error: {}", e),
+            )
+            .map_err(|e| e.to_string())?;
+        stream.play().map_err(|e| e.to_string())?;
+
+        Ok(DeviceOutput {
+            _stream: stream,
+            sample_rate: config.sample_rate.0,
+        })
+    }
+}
+#[cfg(feature = "cpal")]
+impl AudioOutput for DeviceOutput {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    fn pump(&mut self, _mixer: &Mutex<Mixer>, _elapsed: Duration) {}
+}
+
+/// `--audio` option value
+pub enum OutputKind {
+    Null,
+    WavFile(String),
+    #[cfg(feature = "cpal")]
+    Device,
+}
+impl OutputKind {
+    /// `null`, `wav:<path>` or `device`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "null" => Some(OutputKind::Null),
+            #[cfg(feature = "cpal")]
+            "device" => Some(OutputKind::Device),
+            _ => s
+                .strip_prefix("wav:")
+                .map(|p| OutputKind::WavFile(p.to_owned())),
+        }
+    }
+}
+impl Default for OutputKind {
+    #[cfg(feature = "cpal")]
+    fn default() -> Self {
+        OutputKind::Device
+    }
+    #[cfg(not(feature = "cpal"))]
+    fn default() -> Self {
+        OutputKind::Null
+    }
+}
+
+pub struct Audio {
+    mixer: Arc<Mutex<Mixer>>,
+    output: Box<dyn AudioOutput>,
+    sounds: HashMap<String, Arc<Sound>>,
+}
+impl Audio {
+    /// Falls back to the null output if the output cannot be opened
+    pub fn new(kind: &OutputKind) -> Self {
+        let mixer = Arc::new(Mutex::new(Mixer::new()));
+        let output: Result<Box<dyn AudioOutput>, String> = match kind {
+            OutputKind::Null => Ok(Box::new(NullOutput::new())),
+            OutputKind::WavFile(p) => {
+                WavFileOutput::create(p).map(|o| Box::new(o) as _)
+            }
+            #[cfg(feature = "cpal")]
+            OutputKind::Device => {
+                DeviceOutput::open(mixer.clone()).map(|o| Box::new(o) as _)
+            }
+        };
+        let output = output.unwrap_or_else(|e| {
+            log::warn!("Audio output unavailable ({}), sounds are muted", e);
+            Box::new(NullOutput::new())
+        });
+        let sounds = load_sounds(SOUNDS_DIR, output.sample_rate());
+
+        Audio {
+            mixer,
+            output,
+            sounds,
+        }
+    }
+
+    pub fn play(&mut self, name: &str, options: &PlayOptions) {
+        match self.sounds.get(name) {
+            Some(s) => self
+                .mixer
+                .lock()
+                .expect("mixer poisoned")
+                .play(s.clone(), options),
+            None => log::warn!("Unknown sound: {}", name),
+        }
+    }
+
+    pub fn pump(&mut self, elapsed: Duration) {
+        self.output.pump(&self.mixer, elapsed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Constant full scale stereo
+    fn tone(frames: usize) -> Arc<Sound> {
+        Arc::new(Sound {
+            samples: vec![1.0; frames * 2],
+        })
+    }
+
+    fn mixer() -> Mixer {
+        let mut m = Mixer::new();
+        m.master_volume = 1.0;
+        m
+    }
+
+    /// (left, right) gains of a voice panned to `column` of 8
+    fn column_gains(column: f64) -> (f32, f32) {
+        let mut m = mixer();
+        let options = PlayOptions {
+            volume: 0.5,
+            pan: PlayOptions::column_pan(column, 8),
+        };
+        m.play(tone(1), &options);
+        let mut out = [0.0; 2];
+        m.mix(&mut out);
+        (out[0], out[1])
+    }
+
+    #[test]
+    fn equal_power_pan() {
+        let first = column_gains(0.0);
+        let center = column_gains(3.5);
+        let last = column_gains(7.0);
+        for &(l, r) in &[first, center, last] {
+            assert!((l * l + r * r - 0.25).abs() < 1e-6);
+        }
+        assert!((center.0 - center.1).abs() < 1e-6);
+        assert!(first.0 > first.1);
+        assert!((first.0 - last.1).abs() < 1e-6);
+        assert!((first.1 - last.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn resampled_length() {
+        let mono = vec![0.5; 100];
+        let up = Sound::from_interleaved(&mono, 1, 22050, 44100);
+        assert_eq!(up.frames(), 200);
+        assert!(up.samples.iter().all(|&s| s == 0.5));
+        let stereo = vec![0.5; 480 * 2];
+        let down = Sound::from_interleaved(&stereo, 2, 48000, 44100);
+        assert_eq!(down.frames(), 441);
+        assert_eq!(Sound::from_interleaved(&[], 2, 48000, 44100).frames(), 0);
+    }
+
+    #[test]
+    fn finished_voices_are_dropped() {
+        let mut m = mixer();
+        m.play(tone(10), &PlayOptions::default());
+        let mut out = [0.0; 16];
+        m.mix(&mut out);
+        assert_eq!(m.voices.len(), 1);
+        m.mix(&mut out);
+        assert!(m.voices.is_empty());
+        // 2 frames left for the second buffer
+        assert!(out[..4].iter().all(|&s| s != 0.0));
+        assert!(out[4..].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn null_output_consumes_voices() {
+        let mixer = Mutex::new(mixer());
+        mixer
+            .lock()
+            .unwrap()
+            .play(tone(100), &PlayOptions::default());
+        let mut output = NullOutput::new();
+        output.pump(&mixer, Duration::from_millis(10));
+        assert_eq!(output.written_frames, 441);
+        assert!(mixer.lock().unwrap().voices.is_empty());
+    }
+
+    #[test]
+    fn wav_output_records_every_frame() {
+        let path = std::env::temp_dir().join("reversi_audio_test.wav");
+        let path = path.to_str().unwrap();
+        let mixer = Mutex::new(mixer());
+        mixer
+            .lock()
+            .unwrap()
+            .play(tone(100), &PlayOptions::default());
+        {
+            let mut output = WavFileOutput::create(path).unwrap();
+            output.pump(&mixer, Duration::from_millis(10));
+            output.pump(&mixer, Duration::from_millis(20));
+        }
+        let reader = hound::WavReader::open(path).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.duration(), 882);
+        std::fs::remove_file(path).unwrap();
+    }
+}
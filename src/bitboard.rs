@@ -0,0 +1,309 @@
+//! 8x8 positions as pairs of bitboards, used by engines
+//!
+//! Bit `x + y * 8` is the cell (x, y), same order as the board state buffer.
+
+/// Cells in a row. Engines support only this size.
+pub const SIZE: usize = 8;
+
+const NOT_FIRST_COLUMN: u64 = 0xfefe_fefe_fefe_fefe;
+const NOT_LAST_COLUMN: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+/// (shift amount, mask dropping bits wrapped around a row)
+const DIRECTIONS: [(i32, u64); 8] = [
+    (1, NOT_FIRST_COLUMN),
+    (-1, NOT_LAST_COLUMN),
+    (8, !0),
+    (-8, !0),
+    (9, NOT_FIRST_COLUMN),
+    (7, NOT_LAST_COLUMN),
+    (-7, NOT_FIRST_COLUMN),
+    (-9, NOT_LAST_COLUMN),
+];
+
+fn shift(b: u64, (amount, mask): (i32, u64)) -> u64 {
+    if amount > 0 {
+        (b << amount) & mask
+    } else {
+        (b >> -amount) & mask
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Color {
+    Black,
+    White,
+}
+impl Color {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "black" => Some(Color::Black),
+            "white" => Some(Color::White),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Color::Black => "black",
+            Color::White => "white",
+        }
+    }
+
+    pub fn opponent(self) -> Self {
+        match self {
+            Color::Black => Color::White,
+            Color::White => Color::Black,
+        }
+    }
+}
+
+/// Discs seen from the side to move
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Position {
+    pub player: u64,
+    pub opponent: u64,
+}
+impl Position {
+    /// Starting position, black to move
+    pub fn initial() -> Self {
+        // black on d4 and e5, white on e4 and d5
+        Self::from_discs(
+            0x0000_0010_0800_0000,
+            0x0000_0008_1000_0000,
+            Color::Black,
+        )
+    }
+
+    pub fn from_discs(black: u64, white: u64, to_move: Color) -> Self {
+        match to_move {
+            Color::Black => Position {
+                player: black,
+                opponent: white,
+            },
+            Color::White => Position {
+                player: white,
+                opponent: black,
+            },
+        }
+    }
+
+    /// Reads discs from `state_flags` of the board state cells
+    pub fn from_cell_flags(flags: &[u32], to_move: Color) -> Self {
+        use crate::cell_state::{STATE_PLACED, STATE_WHITE};
+
+        let (mut black, mut white) = (0, 0);
+        for (n, &f) in flags.iter().take(SIZE * SIZE).enumerate() {
+            if f & STATE_PLACED == 0 {
+                continue;
+            }
+            if f & STATE_WHITE != 0 {
+                white |= 1 << n;
+            } else {
+                black |= 1 << n;
+            }
+        }
+        Self::from_discs(black, white, to_move)
+    }
+
+    /// Black and white discs, inverse of `from_discs`
+    pub fn discs(&self, to_move: Color) -> (u64, u64) {
+        match to_move {
+            Color::Black => (self.player, self.opponent),
+            Color::White => (self.opponent, self.player),
+        }
+    }
+
+    /// Turns a position seen from black to the side implied to move.
+    /// Black moves first, so it is to move with an even number of discs
+    /// unless someone has passed. A side without legal moves is assumed to
+    /// pass, other passes can not be told from the discs.
+    pub fn implied_turn(self) -> (Position, Color) {
+        let discs = (self.player | self.opponent).count_ones();
+        let (pos, color) = if discs % 2 == 0 {
+            (self, Color::Black)
+        } else {
+            (self.pass(), Color::White)
+        };
+        if pos.legal_moves() == 0 && pos.pass().legal_moves() != 0 {
+            (pos.pass(), color.opponent())
+        } else {
+            (pos, color)
+        }
+    }
+
+    pub fn empties(&self) -> u64 {
+        !(self.player | self.opponent)
+    }
+
+    /// Cells where the side to move can place a disc
+    pub fn legal_moves(&self) -> u64 {
+        let empties = self.empties();
+        let mut moves = 0;
+        for &d in &DIRECTIONS {
+            let mut x = shift(self.player, d) & self.opponent;
+            // at most 6 discs are flipped in a line
+            for _ in 0..5 {
+                x |= shift(x, d) & self.opponent;
+            }
+            moves |= shift(x, d) & empties;
+        }
+        moves
+    }
+
+    /// Opponent discs flipped by a move at `sq`
+    pub fn flips(&self, sq: u32) -> u64 {
+        let mut flips = 0;
+        for &d in &DIRECTIONS {
+            let mut line = 0;
+            let mut x = shift(1 << sq, d);
+            while x & self.opponent != 0 {
+                line |= x;
+                x = shift(x, d);
+            }
+            if x & self.player != 0 {
+                flips |= line;
+            }
+        }
+        flips
+    }
+
+    /// Position after the side to move places at `sq`.
+    /// The move must be legal.
+    pub fn play(&self, sq: u32) -> Position {
+        let flips = self.flips(sq);
+        Position {
+            player: self.opponent & !flips,
+            opponent: self.player | flips | 1 << sq,
+        }
+    }
+
+    /// Position with the turn passed to the opponent
+    pub fn pass(&self) -> Position {
+        Position {
+            player: self.opponent,
+            opponent: self.player,
+        }
+    }
+
+    /// Discs of the side to move minus discs of the opponent.
+    /// Empty cells count for the winner as in tournament scoring.
+    pub fn final_score(&self) -> i32 {
+        let p = self.player.count_ones() as i32;
+        let o = self.opponent.count_ones() as i32;
+        let empties = self.empties().count_ones() as i32;
+        if p > o {
+            p - o + empties
+        } else if p < o {
+            p - o - empties
+        } else {
+            0
+        }
+    }
+}
+
+/// Iterates set bits as cell indices
+pub fn squares(mut b: u64) -> impl Iterator<Item = u32> {
+    std::iter::from_fn(move || {
+        if b == 0 {
+            return None;
+        }
+        let sq = b.trailing_zeros();
+        b &= b - 1;
+        Some(sq)
+    })
+}
+
+/// (x, y) of a cell index
+pub fn square_xy(sq: u32) -> (u32, u32) {
+    (sq % SIZE as u32, sq / SIZE as u32)
+}
+
+/// Flips a cell index between this board and the standard orientation,
+/// where black starts on d5 and e4. Cell names in records, books and solver
+/// formats are in the standard orientation.
+pub fn standard_square(sq: u32) -> u32 {
+    sq ^ 56
+}
+
+/// Cell index of a name like `c4` (case insensitive)
+pub fn parse_square_name(name: &str) -> Option<u32> {
+    let mut chars = name.chars();
+    let column = chars.next()?.to_ascii_lowercase();
+    let row = chars.next()?.to_digit(10)?;
+    if chars.next().is_some()
+        || !('a'..='h').contains(&column)
+        || !(1..=8).contains(&row)
+    {
+        return None;
+    }
+    Some((column as u32 - 'a' as u32) + (row - 1) * SIZE as u32)
+}
+
+/// Conventional name of a cell: column letter and 1-based row (`c4`)
+pub fn square_name(sq: u32) -> String {
+    let (x, y) = square_xy(sq);
+    format!("{}{}", (b'a' + x as u8) as char, y + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Leaves at `depth` plies, a pass counting as a ply
+    fn perft(pos: &Position, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = pos.legal_moves();
+        if moves == 0 {
+            let passed = pos.pass();
+            if passed.legal_moves() == 0 {
+                return 1;
+            }
+            return perft(&passed, depth - 1);
+        }
+        squares(moves)
+            .map(|sq| perft(&pos.play(sq), depth - 1))
+            .sum()
+    }
+
+    #[test]
+    fn perft_from_initial() {
+        let counts: Vec<u64> =
+            (1..=7).map(|d| perft(&Position::initial(), d)).collect();
+        assert_eq!(counts, [4, 12, 56, 244, 1396, 8200, 55092]);
+    }
+
+    #[test]
+    fn first_moves_flip_one_disc() {
+        let pos = Position::initial();
+        for sq in squares(pos.legal_moves()) {
+            assert_eq!(pos.flips(sq).count_ones(), 1);
+            let next = pos.play(sq);
+            assert_eq!(next.opponent.count_ones(), 4);
+            assert_eq!(next.player.count_ones(), 1);
+        }
+    }
+
+    #[test]
+    fn standard_square_names() {
+        // black starts on d5 and e4 in the standard orientation
+        let (black, _) = Position::initial().discs(Color::Black);
+        let mut names: Vec<String> = squares(black)
+            .map(|sq| square_name(standard_square(sq)))
+            .collect();
+        names.sort();
+        assert_eq!(names, ["d5", "e4"]);
+        assert_eq!(parse_square_name("F5"), Some(37));
+        assert_eq!(parse_square_name("i1"), None);
+        assert_eq!(square_name(37), "f5");
+    }
+
+    #[test]
+    fn implied_turn_after_one_move() {
+        let (black, white) = Position::initial().play(19).discs(Color::White);
+        let (pos, color) =
+            Position::from_discs(black, white, Color::Black).implied_turn();
+        assert_eq!(color, Color::White);
+        assert_eq!(pos, Position::initial().play(19));
+    }
+}
@@ -0,0 +1,279 @@
+//! Opening book: candidate moves with weights and scores by position
+//!
+//! Positions are stored in a canonical orientation, the smallest of the 8
+//! board symmetries, so that transposed or mirrored openings share entries.
+//! Moves are stored in the same orientation, named as in the standard one
+//! (see `bitboard::standard_square`).
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bitboard::{
+    parse_square_name, square_name, standard_square, Position,
+};
+use crate::record::GameRecord;
+
+pub const BOOK_PATH: &'static str = "./assets/book.json";
+/// Moves after this many plies of a transcript are not recorded
+const BOOK_PLIES: usize = 20;
+
+fn transpose(b: u64) -> u64 {
+    const K1: u64 = 0x5500_5500_5500_5500;
+    const K2: u64 = 0x3333_0000_3333_0000;
+    const K4: u64 = 0x0f0f_0f0f_0000_0000;
+    let mut b = b;
+    let t = K4 & (b ^ (b << 28));
+    b ^= t ^ (t >> 28);
+    let t = K2 & (b ^ (b << 14));
+    b ^= t ^ (t >> 14);
+    let t = K1 & (b ^ (b << 7));
+    b ^ t ^ (t >> 7)
+}
+fn flip_vertical(b: u64) -> u64 {
+    b.swap_bytes()
+}
+fn mirror_horizontal(b: u64) -> u64 {
+    const K1: u64 = 0x5555_5555_5555_5555;
+    const K2: u64 = 0x3333_3333_3333_3333;
+    const K4: u64 = 0x0f0f_0f0f_0f0f_0f0f;
+    let b = ((b >> 1) & K1) | ((b & K1) << 1);
+    let b = ((b >> 2) & K2) | ((b & K2) << 2);
+    ((b >> 4) & K4) | ((b & K4) << 4)
+}
+
+/// Applies symmetry `s` (0..8): bit 0 transposes, bit 1 flips vertically
+/// and bit 2 mirrors horizontally, in this order
+fn apply_symmetry(b: u64, s: u8) -> u64 {
+    let b = if s & 1 != 0 { transpose(b) } else { b };
+    let b = if s & 2 != 0 { flip_vertical(b) } else { b };
+    if s & 4 != 0 {
+        mirror_horizontal(b)
+    } else {
+        b
+    }
+}
+fn invert_symmetry(b: u64, s: u8) -> u64 {
+    let b = if s & 4 != 0 { mirror_horizontal(b) } else { b };
+    let b = if s & 2 != 0 { flip_vertical(b) } else { b };
+    if s & 1 != 0 {
+        transpose(b)
+    } else {
+        b
+    }
+}
+
+/// The canonical orientation of the position and the symmetry leading to it
+fn canonicalize(pos: &Position) -> (Position, u8) {
+    (0..8)
+        .map(|s| {
+            let p = Position {
+                player: apply_symmetry(pos.player, s),
+                opponent: apply_symmetry(pos.opponent, s),
+            };
+            (p, s)
+        })
+        .min_by_key(|(p, _)| (p.player, p.opponent))
+        .expect("no symmetries")
+}
+
+/// splitmix64 finalizer
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+fn position_hash(canonical: &Position) -> u64 {
+    mix(canonical.player ^ mix(canonical.opponent))
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BookMove {
+    /// Cell in the canonical orientation (`c4`)
+    pub cell: String,
+    /// Number of games playing the move
+    pub weight: u32,
+    /// Mean final disc differential for the side playing the move,
+    /// None if no game through the move was played to the end
+    pub score: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BookEntry {
+    hash: u64,
+    moves: Vec<BookMove>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BookFile {
+    entries: Vec<BookEntry>,
+}
+
+pub struct Book {
+    entries: HashMap<u64, Vec<BookMove>>,
+}
+impl Book {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to load book {}: {}", path, e))?;
+        let file: BookFile = serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse book {}: {}", path, e))?;
+        Ok(Book {
+            entries: file
+                .entries
+                .into_iter()
+                .map(|e| (e.hash, e.moves))
+                .collect(),
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut entries: Vec<_> = self
+            .entries
+            .iter()
+            .map(|(&hash, moves)| BookEntry {
+                hash,
+                moves: moves.clone(),
+            })
+            .collect();
+        // stable output for version control
+        entries.sort_by_key(|e| e.hash);
+        let text = serde_json::to_string_pretty(&BookFile { entries })
+            .map_err(|e| format!("Failed to serialize book: {}", e))?;
+        std::fs::write(path, text)
+            .map_err(|e| format!("Failed to save book {}: {}", path, e))
+    }
+
+    /// Candidate moves for the side to move as (cell index, move)
+    pub fn lookup(&self, pos: &Position) -> Vec<(u32, &BookMove)> {
+        let (canonical, s) = canonicalize(pos);
+        let moves = match self.entries.get(&position_hash(&canonical)) {
+            Some(m) => m,
+            None => return Vec::new(),
+        };
+        let legal = pos.legal_moves();
+        moves
+            .iter()
+            .filter_map(|m| {
+                let sq = standard_square(parse_square_name(&m.cell)?);
+                let sq = invert_symmetry(1 << sq, s).trailing_zeros();
+                // hash collisions
+                if legal & 1 << sq == 0 {
+                    return None;
+                }
+                Some((sq, m))
+            })
+            .collect()
+    }
+
+    /// Picks a move with probability proportional to its weight.
+    /// `r` is uniformly distributed in 0..1.
+    pub fn pick(&self, pos: &Position, r: f64) -> Option<u32> {
+        let moves = self.lookup(pos);
+        let total: u32 = moves.iter().map(|(_, m)| m.weight).sum();
+        let mut threshold = (r * total as f64) as u32;
+        for (sq, m) in moves {
+            if threshold < m.weight {
+                return Some(sq);
+            }
+            threshold -= m.weight;
+        }
+        None
+    }
+
+    /// Builds a book from game transcripts, one game per line as
+    /// transcripts from `Position::initial` (`f5d6c3...`). Lines starting
+    /// with `#` are ignored.
+    pub fn build(transcripts: &str) -> Result<Self, String> {
+        struct Stats {
+            weight: u32,
+            score_sum: f32,
+            finished: u32,
+        }
+        let mut stats: HashMap<u64, HashMap<u32, Stats>> = HashMap::new();
+
+        for (n, line) in transcripts.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let record = GameRecord::parse_transcript(line)
+                .map_err(|e| format!("line {}: {}", n + 1, e))?;
+
+            // (hash, canonical cell, played by black)
+            let mut played = Vec::new();
+            let mut pos = record.start;
+            let mut black_to_move = true;
+            // passes are explicit in records
+            for m in &record.moves {
+                match *m {
+                    Some(sq) => {
+                        if played.len() < BOOK_PLIES {
+                            let (canonical, s) = canonicalize(&pos);
+                            let cell =
+                                apply_symmetry(1 << sq, s).trailing_zeros();
+                            played.push((
+                                position_hash(&canonical),
+                                cell,
+                                black_to_move,
+                            ));
+                        }
+                        pos = pos.play(sq);
+                    }
+                    None => pos = pos.pass(),
+                }
+                black_to_move = !black_to_move;
+            }
+
+            let finished =
+                pos.legal_moves() == 0 && pos.pass().legal_moves() == 0;
+            // final_score is from the side to move
+            let black_score = if black_to_move {
+                pos.final_score()
+            } else {
+                -pos.final_score()
+            };
+            for (hash, cell, by_black) in played {
+                let s = stats.entry(hash).or_default().entry(cell).or_insert(
+                    Stats {
+                        weight: 0,
+                        score_sum: 0.0,
+                        finished: 0,
+                    },
+                );
+                s.weight += 1;
+                if finished {
+                    let score =
+                        if by_black { black_score } else { -black_score };
+                    s.score_sum += score as f32;
+                    s.finished += 1;
+                }
+            }
+        }
+
+        let entries = stats
+            .into_iter()
+            .map(|(hash, moves)| {
+                let mut moves: Vec<_> = moves
+                    .into_iter()
+                    .map(|(cell, s)| BookMove {
+                        cell: square_name(standard_square(cell)),
+                        weight: s.weight,
+                        score: if s.finished > 0 {
+                            Some(s.score_sum / s.finished as f32)
+                        } else {
+                            None
+                        },
+                    })
+                    .collect();
+                moves.sort_by(|a, b| {
+                    b.weight.cmp(&a.weight).then_with(|| a.cell.cmp(&b.cell))
+                });
+                (hash, moves)
+            })
+            .collect();
+        Ok(Book { entries })
+    }
+}
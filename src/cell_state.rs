@@ -0,0 +1,91 @@
+//! Per-cell board state shared with shaders and scripts.
+//!
+//! This file is the source of truth of the layout: `build.rs` reads it and
+//! generates the GLSL block declaration (`assets/generated/board_state.glsl`)
+//! and the TypeScript accessor (`scripts/src/generated/cellState.ts`). Both
+//! are checked in; after changing the layout, build once with
+//! `UPDATE_GENERATED=1` to regenerate them.
+
+use crate::std140::Std140;
+
+/// Number of cells in the `BoardState` uniform block, enough for the largest
+/// board. Smaller boards use the first `size * size` cells.
+pub const BOARD_CELL_COUNT: usize = 12 * 12;
+/// Largest number of cells in a row
+pub const MAX_BOARD_SIZE: usize = 12;
+/// Board sizes selectable at startup. Even sizes only, so that the initial
+/// four stones sit at the center.
+pub const BOARD_SIZES: &'static [usize] = &[6, 8, 10, 12];
+
+/// Index of the cell at (x, y) on a board with `board_size` cells in a row
+/// (cells are stored in row-major order), None if out of the board
+pub fn cell_index(board_size: usize, x: i64, y: i64) -> Option<usize> {
+    let size = board_size as i64;
+    if 0 <= x && x < size && 0 <= y && y < size {
+        Some((x + y * size) as usize)
+    } else {
+        None
+    }
+}
+
+/// `state_flags`: a stone is on the cell
+pub const STATE_PLACED: u32 = 0x80;
+/// `state_flags`: the stone is white
+pub const STATE_WHITE: u32 = 0x01;
+
+/// Reads `state_flags` of cells from a board state buffer
+pub fn read_state_flags(bytes: &[u8]) -> Vec<u32> {
+    let offset = <CellState as Std140>::FIELDS
+        .iter()
+        .find(|f| f.name == "stateFlags")
+        .expect("no stateFlags field")
+        .offset;
+    bytes
+        .chunks_exact(<CellState as Std140>::ARRAY_STRIDE)
+        .map(|c| {
+            let mut b = [0; 4];
+            b.copy_from_slice(&c[offset..offset + 4]);
+            u32::from_ne_bytes(b)
+        })
+        .collect()
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Std140)]
+pub struct CellState {
+    pub state_flags: u32,
+    /// 0 = flip just started, 1 = settled (driven by tweens)
+    pub flip_progress: f32,
+    /// 0 = dropping from above, 1 = landed (driven by tweens)
+    pub place_progress: f32,
+}
+
+// CellState::STD140_SIZE, STD140_ARRAY_STRIDE, STD140_FIELD_OFFSETS
+include!(concat!(env!("OUT_DIR"), "/cell_state_layout.rs"));
+
+const _: () = assert!(
+    MAX_BOARD_SIZE * MAX_BOARD_SIZE == BOARD_CELL_COUNT,
+    "BOARD_CELL_COUNT must be MAX_BOARD_SIZE squared"
+);
+
+// the generated GLSL and TypeScript layouts must agree with the derived one
+const _: () = {
+    let fields = <CellState as Std140>::FIELDS;
+    let offsets = CellState::STD140_FIELD_OFFSETS;
+    assert!(
+        fields.len() == offsets.len(),
+        "generated CellState has different number of fields"
+    );
+    let mut n = 0;
+    while n < fields.len() {
+        assert!(
+            fields[n].offset == offsets[n],
+            "generated CellState field offset does not match"
+        );
+        n += 1;
+    }
+    assert!(
+        CellState::STD140_ARRAY_STRIDE == <CellState as Std140>::ARRAY_STRIDE,
+        "generated CellState array stride does not match"
+    );
+};
@@ -0,0 +1,224 @@
+//! Chess clock style time controls
+//!
+//! Each side has a main time that may grow by an increment after every move.
+//! Once it runs out, a side in byoyomi has a fixed period for each move,
+//! renewed after the move. The flag falls when the last of both runs out.
+//! Time is measured against the script clock and charged by `tick`.
+
+use crate::bitboard::Color;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TimeControl {
+    pub main_ms: f64,
+    /// Added to the main time after each move made before it ran out
+    pub increment_ms: f64,
+    /// Period for each move after the main time, 0 for none
+    pub byoyomi_ms: f64,
+}
+impl TimeControl {
+    /// `<main>[+<increment>][/<byoyomi>]` in seconds, e.g. `300+5` or
+    /// `600/30`
+    pub fn parse(s: &str) -> Option<Self> {
+        let seconds = |s: &str| {
+            s.parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite() && *v >= 0.0)
+                .map(|v| v * 1000.0)
+        };
+        let (rest, byoyomi_ms) = match s.find('/') {
+            Some(n) => (&s[..n], seconds(&s[n + 1..])?),
+            None => (s, 0.0),
+        };
+        let (main_ms, increment_ms) = match rest.find('+') {
+            Some(n) => (seconds(&rest[..n])?, seconds(&rest[n + 1..])?),
+            None => (seconds(rest)?, 0.0),
+        };
+        if main_ms == 0.0 && byoyomi_ms == 0.0 {
+            return None;
+        }
+        Some(TimeControl {
+            main_ms,
+            increment_ms,
+            byoyomi_ms,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SideClock {
+    pub main_ms: f64,
+    /// Left of the current byoyomi period
+    pub byoyomi_ms: f64,
+}
+impl SideClock {
+    pub fn in_byoyomi(&self) -> bool {
+        self.main_ms <= 0.0
+    }
+
+    /// Time left for the current move
+    pub fn remaining_ms(&self) -> f64 {
+        if self.in_byoyomi() {
+            self.byoyomi_ms
+        } else {
+            self.main_ms
+        }
+    }
+}
+
+pub struct GameClock {
+    control: TimeControl,
+    black: SideClock,
+    white: SideClock,
+    running: Option<Color>,
+    last_tick_ms: f64,
+}
+impl GameClock {
+    pub fn new(control: TimeControl) -> Self {
+        let side = SideClock {
+            main_ms: control.main_ms,
+            byoyomi_ms: control.byoyomi_ms,
+        };
+        GameClock {
+            control,
+            black: side,
+            white: side,
+            running: None,
+            last_tick_ms: 0.0,
+        }
+    }
+
+    pub fn side(&self, color: Color) -> &SideClock {
+        match color {
+            Color::Black => &self.black,
+            Color::White => &self.white,
+        }
+    }
+
+    fn side_mut(&mut self, color: Color) -> &mut SideClock {
+        match color {
+            Color::Black => &mut self.black,
+            Color::White => &mut self.white,
+        }
+    }
+
+    /// Side whose time is running
+    pub fn running(&self) -> Option<Color> {
+        self.running
+    }
+
+    /// Starts the time of the side to move
+    pub fn start(&mut self, color: Color, now_ms: f64) {
+        self.running = Some(color);
+        self.last_tick_ms = now_ms;
+    }
+
+    pub fn stop(&mut self) {
+        self.running = None;
+    }
+
+    /// Stops the time of `color` after a move, adding the increment or
+    /// renewing the byoyomi period
+    pub fn complete_move(&mut self, color: Color) {
+        let control = self.control;
+        let side = self.side_mut(color);
+        if side.in_byoyomi() {
+            side.byoyomi_ms = control.byoyomi_ms;
+        } else {
+            side.main_ms += control.increment_ms;
+        }
+        if self.running == Some(color) {
+            self.running = None;
+        }
+    }
+
+    /// Charges the time since the last tick to the running side, nothing if
+    /// `paused`. Returns the side whose flag fell.
+    pub fn tick(&mut self, now_ms: f64, paused: bool) -> Option<Color> {
+        let elapsed = now_ms - self.last_tick_ms;
+        self.last_tick_ms = now_ms;
+        let color = self.running?;
+        if paused {
+            return None;
+        }
+
+        let side = self.side_mut(color);
+        let overflow = (elapsed - side.main_ms).max(0.0);
+        side.main_ms = (side.main_ms - elapsed).max(0.0);
+        side.byoyomi_ms = (side.byoyomi_ms - overflow).max(0.0);
+        if side.in_byoyomi() && side.byoyomi_ms <= 0.0 {
+            self.running = None;
+            Some(color)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_time_controls() {
+        let c = TimeControl::parse("300+5/30").unwrap();
+        assert_eq!(c.main_ms, 300_000.0);
+        assert_eq!(c.increment_ms, 5_000.0);
+        assert_eq!(c.byoyomi_ms, 30_000.0);
+        assert_eq!(TimeControl::parse("0.5").unwrap().main_ms, 500.0);
+        assert_eq!(TimeControl::parse("0/10").unwrap().byoyomi_ms, 10_000.0);
+        assert!(TimeControl::parse("0").is_none());
+        assert!(TimeControl::parse("5+").is_none());
+        assert!(TimeControl::parse("-5").is_none());
+    }
+
+    #[test]
+    fn increment_after_each_move() {
+        let mut clock = GameClock::new(TimeControl::parse("10+2").unwrap());
+        clock.start(Color::Black, 0.0);
+        assert_eq!(clock.tick(3_000.0, false), None);
+        clock.complete_move(Color::Black);
+        assert_eq!(clock.side(Color::Black).remaining_ms(), 9_000.0);
+        assert_eq!(clock.running(), None);
+        assert_eq!(clock.side(Color::White).remaining_ms(), 10_000.0);
+    }
+
+    #[test]
+    fn paused_time_is_not_charged() {
+        let mut clock = GameClock::new(TimeControl::parse("10").unwrap());
+        clock.start(Color::White, 0.0);
+        assert_eq!(clock.tick(4_000.0, true), None);
+        assert_eq!(clock.tick(5_000.0, false), None);
+        assert_eq!(clock.side(Color::White).remaining_ms(), 9_000.0);
+    }
+
+    #[test]
+    fn byoyomi_is_renewed_after_moves() {
+        let mut clock = GameClock::new(TimeControl::parse("1/2").unwrap());
+        clock.start(Color::Black, 0.0);
+        // 0.5s into byoyomi
+        assert_eq!(clock.tick(1_500.0, false), None);
+        let black = clock.side(Color::Black);
+        assert!(black.in_byoyomi());
+        assert_eq!(black.remaining_ms(), 1_500.0);
+        clock.complete_move(Color::Black);
+        assert_eq!(clock.side(Color::Black).remaining_ms(), 2_000.0);
+    }
+
+    #[test]
+    fn flag_falls_when_byoyomi_runs_out() {
+        let mut clock = GameClock::new(TimeControl::parse("1/2").unwrap());
+        clock.start(Color::Black, 0.0);
+        assert_eq!(clock.tick(2_999.0, false), None);
+        assert_eq!(clock.tick(3_000.0, false), Some(Color::Black));
+        assert_eq!(clock.running(), None);
+        // stopped after the flag fell
+        assert_eq!(clock.tick(4_000.0, false), None);
+    }
+
+    #[test]
+    fn flag_falls_without_byoyomi() {
+        let mut clock = GameClock::new(TimeControl::parse("1+5").unwrap());
+        clock.start(Color::White, 0.0);
+        assert_eq!(clock.tick(1_000.0, false), Some(Color::White));
+    }
+}
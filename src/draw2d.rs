@@ -0,0 +1,334 @@
+//! Immediate mode 2D batcher filled by scripts every frame
+//!
+//! Coordinates are in window pixels with the origin at the top left, same as
+//! `cursorPos()`. Shapes are quads whose coverage is computed from a signed
+//! distance in `draw2d.fsh`.
+
+use std::collections::HashMap;
+
+use crate::glres::{
+    ArrayBufferTarget, Buffer, GlObject, Texture, VertexArray, ARRAY_BUFFER,
+};
+use crate::shader::{Program, TextureUnit};
+
+/// Mode of a vertex: distance based shape or textured quad
+const MODE_SHAPE: f32 = 0.0;
+const MODE_TEXTURED: f32 = 1.0;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Vertex2D {
+    position: [f32; 2],
+    /// Offset from the shape center in pixels, or texture coordinates
+    local: [f32; 2],
+    half_size: [f32; 2],
+    /// (corner radius, mode)
+    params: [f32; 2],
+    color: [f32; 4],
+}
+
+#[derive(Clone)]
+struct Batch {
+    /// 0 = untextured
+    texture: u32,
+    first: usize,
+    count: usize,
+}
+
+struct DecodedImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+/// Commands issued by scripts in the current frame
+pub struct DrawList {
+    vertices: Vec<Vertex2D>,
+    batches: Vec<Batch>,
+    next_texture_id: u32,
+    /// Decoded images waiting for upload
+    pending_textures: Vec<(u32, DecodedImage)>,
+}
+impl DrawList {
+    pub fn new() -> Self {
+        DrawList {
+            vertices: Vec::new(),
+            batches: Vec::new(),
+            next_texture_id: 1,
+            pending_textures: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.batches.clear();
+    }
+
+    /// Pushes a quad centered at `center` rotated by `(cos, sin)`
+    fn quad(
+        &mut self,
+        texture: u32,
+        center: [f32; 2],
+        half_size: [f32; 2],
+        (cos, sin): (f32, f32),
+        params: [f32; 2],
+        color: [f32; 4],
+    ) {
+        // 1px margin for antialiased edges
+        let [hx, hy] = if params[1] == MODE_TEXTURED {
+            half_size
+        } else {
+            [half_size[0] + 1.0, half_size[1] + 1.0]
+        };
+        let corners = [[-hx, -hy], [hx, -hy], [-hx, hy], [hx, hy]];
+        let vertex = |n: usize| {
+            let [lx, ly] = corners[n];
+            let local = if params[1] == MODE_TEXTURED {
+                [(n % 2) as f32, (n / 2) as f32]
+            } else {
+                [lx, ly]
+            };
+            Vertex2D {
+                position: [
+                    center[0] + lx * cos - ly * sin,
+                    center[1] + lx * sin + ly * cos,
+                ],
+                local,
+                half_size,
+                params,
+                color,
+            }
+        };
+
+        match self.batches.last_mut() {
+            Some(b) if b.texture == texture => b.count += 6,
+            _ => self.batches.push(Batch {
+                texture,
+                first: self.vertices.len(),
+                count: 6,
+            }),
+        }
+        self.vertices
+            .extend([0, 1, 2, 2, 1, 3].iter().map(|&n| vertex(n)));
+    }
+
+    pub fn rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: [f32; 4]) {
+        self.rounded_rect(x, y, w, h, 0.0, color);
+    }
+    pub fn rounded_rect(
+        &mut self,
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+        radius: f32,
+        color: [f32; 4],
+    ) {
+        let half_size = [w * 0.5, h * 0.5];
+        let radius = radius.min(half_size[0]).min(half_size[1]).max(0.0);
+        self.quad(
+            0,
+            [x + half_size[0], y + half_size[1]],
+            half_size,
+            (1.0, 0.0),
+            [radius, MODE_SHAPE],
+            color,
+        );
+    }
+    pub fn circle(&mut self, cx: f32, cy: f32, radius: f32, color: [f32; 4]) {
+        self.quad(
+            0,
+            [cx, cy],
+            [radius, radius],
+            (1.0, 0.0),
+            [radius, MODE_SHAPE],
+            color,
+        );
+    }
+    /// Line with round caps
+    pub fn line(
+        &mut self,
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+        width: f32,
+        color: [f32; 4],
+    ) {
+        let (dx, dy) = (x1 - x0, y1 - y0);
+        let length = (dx * dx + dy * dy).sqrt();
+        let dir = if length > 0.0 {
+            (dx / length, dy / length)
+        } else {
+            (1.0, 0.0)
+        };
+        let r = width * 0.5;
+        self.quad(
+            0,
+            [(x0 + x1) * 0.5, (y0 + y1) * 0.5],
+            [length * 0.5 + r, r],
+            dir,
+            [r, MODE_SHAPE],
+            color,
+        );
+    }
+    /// Draws a texture returned by `load_texture`, modulated by `color`
+    pub fn image(
+        &mut self,
+        texture: u32,
+        x: f32,
+        y: f32,
+        w: f32,
+        h: f32,
+        color: [f32; 4],
+    ) {
+        self.quad(
+            texture,
+            [x + w * 0.5, y + h * 0.5],
+            [w * 0.5, h * 0.5],
+            (1.0, 0.0),
+            [0.0, MODE_TEXTURED],
+            color,
+        );
+    }
+
+    /// Decodes a PNG file and returns the id of the texture.
+    /// The texture is uploaded before the next draw.
+    pub fn load_texture(&mut self, path: &str) -> Result<u32, String> {
+        let image = decode_png(path)
+            .map_err(|e| format!("Failed to load texture {}: {}", path, e))?;
+        let id = self.next_texture_id;
+        self.next_texture_id += 1;
+        self.pending_textures.push((id, image));
+        Ok(id)
+    }
+}
+
+fn decode_png(path: &str) -> Result<DecodedImage, Box<dyn std::error::Error>> {
+    let mut decoder = png::Decoder::new(std::fs::File::open(path)?);
+    // palette and low bit depths to 8 bits per channel
+    decoder.set_transformations(
+        png::Transformations::EXPAND | png::Transformations::STRIP_16,
+    );
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf)?;
+
+    let pixels = match info.color_type {
+        png::ColorType::RGBA => buf,
+        png::ColorType::RGB => buf
+            .chunks(3)
+            .flat_map(|p| vec![p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks(2)
+            .flat_map(|p| vec![p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => {
+            buf.iter().flat_map(|&p| vec![p, p, p, 255]).collect()
+        }
+        png::ColorType::Indexed => {
+            return Err("indexed color was not expanded".into())
+        }
+    };
+
+    Ok(DecodedImage {
+        width: info.width,
+        height: info.height,
+        pixels,
+    })
+}
+
+pub struct Renderer2D {
+    vb: Buffer<ArrayBufferTarget>,
+    va: VertexArray,
+    textures: HashMap<u32, Texture>,
+    batches: Vec<Batch>,
+}
+impl Renderer2D {
+    pub fn new() -> Self {
+        const STRIDE: usize = std::mem::size_of::<Vertex2D>();
+        // (location, components, offset in floats)
+        const ATTRIBUTES: &'static [(u32, i32, usize)] =
+            &[(0, 2, 0), (1, 2, 2), (2, 2, 4), (3, 2, 6), (4, 4, 8)];
+
+        let vb = Buffer::new();
+        let va = VertexArray::new();
+        va.bind();
+        ARRAY_BUFFER.bind(&vb);
+        for &(location, components, offset) in ATTRIBUTES {
+            unsafe {
+                gl::EnableVertexAttribArray(location);
+                gl::VertexAttribPointer(
+                    location,
+                    components,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    STRIDE as _,
+                    (offset * std::mem::size_of::<f32>()) as _,
+                );
+            }
+        }
+        VertexArray::unbind();
+        ARRAY_BUFFER.unbind();
+
+        vb.set_label("2D Draw List Vertices");
+        va.set_label("2D Draw List");
+
+        Renderer2D {
+            vb,
+            va,
+            textures: HashMap::new(),
+            batches: Vec::new(),
+        }
+    }
+
+    /// Uploads vertices and new textures of the draw list
+    pub fn upload(&mut self, list: &mut DrawList) {
+        for (id, image) in list.pending_textures.drain(..) {
+            let tex = Texture::new();
+            tex.bind(0).image_rgba8(
+                image.width as _,
+                image.height as _,
+                &image.pixels,
+            );
+            tex.set_label(&format!("2D Texture #{}", id));
+            self.textures.insert(id, tex);
+        }
+
+        ARRAY_BUFFER
+            .bind(&self.vb)
+            .data(&list.vertices, gl::STREAM_DRAW)
+            .unbind();
+        self.batches = list.batches.clone();
+    }
+
+    /// Draws the uploaded list with alpha blending over the current frame
+    pub fn draw(&self, program: &Program, viewport_size: [f32; 2]) {
+        if self.batches.is_empty() {
+            return;
+        }
+
+        program
+            .use_program()
+            .set_uniform("viewport_size", viewport_size)
+            .set_uniform("image", TextureUnit(0));
+        self.va.bind();
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+        for b in &self.batches {
+            if let Some(t) = self.textures.get(&b.texture) {
+                t.bind(0);
+            }
+            unsafe {
+                gl::DrawArrays(gl::TRIANGLES, b.first as _, b.count as _);
+            }
+        }
+        unsafe {
+            gl::Disable(gl::BLEND);
+        }
+        VertexArray::unbind();
+    }
+}
@@ -0,0 +1,244 @@
+//! Exact endgame solver
+//!
+//! Searches to the end of the game for the exact final disc differential.
+//! Moves are ordered by the transposition table, then fastest-first (fewest
+//! opponent replies) and parity (odd regions first) near the leaves.
+
+use std::collections::HashMap;
+
+use crate::bitboard::{squares, Color, Position};
+
+/// Positions with more empty cells than this are refused by callers
+pub const MAX_SOLVE_EMPTIES: u32 = 20;
+/// Below this many empties moves are ordered by parity only
+const FASTEST_FIRST_EMPTIES: u32 = 5;
+/// Below this many empties the transposition table is not used
+const TABLE_EMPTIES: u32 = 6;
+
+/// Quadrants used for parity. An odd number of empties in a region means
+/// the side to move there is likely to get its last move.
+const REGIONS: [u64; 4] = [
+    0x0000_0000_0f0f_0f0f,
+    0x0000_0000_f0f0_f0f0,
+    0x0f0f_0f0f_0000_0000,
+    0xf0f0_f0f0_0000_0000,
+];
+
+pub struct Solution {
+    /// None if the side to move has to pass or the game is over
+    pub best_move: Option<u32>,
+    /// Final disc differential from the side to move with perfect play
+    pub score: i32,
+    pub nodes: u64,
+}
+
+/// "black wins by 12" for a disc differential of `to_move`
+pub fn describe_result(score: i32, to_move: Color) -> String {
+    match score {
+        0 => "draw".to_owned(),
+        s if s > 0 => format!("{} wins by {}", to_move.name(), s),
+        s => format!("{} wins by {}", to_move.opponent().name(), -s),
+    }
+}
+
+/// Bounds of the exact score of a position
+#[derive(Clone, Copy)]
+struct Entry {
+    lower: i32,
+    upper: i32,
+    best_move: Option<u32>,
+}
+
+struct Solver {
+    table: HashMap<Position, Entry>,
+    nodes: u64,
+}
+impl Solver {
+    /// Moves in search order, `first` in front if legal
+    fn ordered_moves(
+        &self,
+        pos: &Position,
+        moves: u64,
+        first: Option<u32>,
+    ) -> Vec<u32> {
+        let empties = pos.empties();
+        let odd_regions = REGIONS
+            .iter()
+            .filter(|&&r| (empties & r).count_ones() % 2 == 1)
+            .fold(0, |a, r| a | r);
+        let mut list: Vec<_> = squares(moves)
+            .map(|sq| {
+                let even = odd_regions & 1 << sq == 0;
+                let replies = if empties.count_ones() >= FASTEST_FIRST_EMPTIES {
+                    pos.play(sq).legal_moves().count_ones()
+                } else {
+                    0
+                };
+                (sq, (Some(sq) != first, replies, even))
+            })
+            .collect();
+        list.sort_by_key(|&(_, key)| key);
+        list.into_iter().map(|(sq, _)| sq).collect()
+    }
+
+    fn solve(
+        &mut self,
+        pos: &Position,
+        mut alpha: i32,
+        mut beta: i32,
+        passed: bool,
+    ) -> (i32, Option<u32>) {
+        self.nodes += 1;
+        let moves = pos.legal_moves();
+        if moves == 0 {
+            if passed {
+                return (pos.final_score(), None);
+            }
+            let (v, _) = self.solve(&pos.pass(), -beta, -alpha, true);
+            return (-v, None);
+        }
+
+        let use_table = pos.empties().count_ones() >= TABLE_EMPTIES;
+        let mut hint = None;
+        if use_table {
+            if let Some(e) = self.table.get(pos) {
+                if e.lower >= beta || e.lower == e.upper {
+                    return (e.lower, e.best_move);
+                }
+                if e.upper <= alpha {
+                    return (e.upper, e.best_move);
+                }
+                alpha = alpha.max(e.lower);
+                beta = beta.min(e.upper);
+                hint = e.best_move;
+            }
+        }
+
+        let original_alpha = alpha;
+        let mut best = (-i32::MAX, None);
+        for sq in self.ordered_moves(pos, moves, hint) {
+            let (v, _) = self.solve(&pos.play(sq), -beta, -alpha, false);
+            let v = -v;
+            if v > best.0 {
+                best = (v, Some(sq));
+            }
+            alpha = alpha.max(v);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        if use_table {
+            let e = self.table.entry(*pos).or_insert(Entry {
+                lower: -i32::MAX,
+                upper: i32::MAX,
+                best_move: None,
+            });
+            if best.0 <= original_alpha {
+                e.upper = best.0;
+            } else if best.0 >= beta {
+                e.lower = best.0;
+            } else {
+                e.lower = best.0;
+                e.upper = best.0;
+            }
+            e.best_move = best.1;
+        }
+        best
+    }
+}
+
+/// Solves the position exactly. Time grows exponentially with the number of
+/// empties, see `MAX_SOLVE_EMPTIES`.
+pub fn solve(pos: &Position) -> Solution {
+    let mut solver = Solver {
+        table: HashMap::new(),
+        nodes: 0,
+    };
+    // Null window searches are far cheaper than a full window one, so the
+    // score is narrowed down with them as in MTD(f). The table carries the
+    // bounds between iterations.
+    let (mut lower, mut upper) = (-64, 64);
+    let mut guess = 0;
+    let mut best_move = None;
+    while lower < upper {
+        let beta = if guess == lower { guess + 1 } else { guess };
+        let (v, m) = solver.solve(pos, beta - 1, beta, false);
+        if v < beta {
+            upper = v;
+            // the move is exact only if none fails high
+            best_move = best_move.or(m);
+        } else {
+            lower = v;
+            best_move = m;
+        }
+        guess = v;
+    }
+    Solution {
+        best_move,
+        score: lower,
+        nodes: solver.nodes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plain negamax to the end of the game
+    fn minimax(pos: &Position) -> i32 {
+        let moves = pos.legal_moves();
+        if moves == 0 {
+            let passed = pos.pass();
+            if passed.legal_moves() == 0 {
+                return pos.final_score();
+            }
+            return -minimax(&passed);
+        }
+        squares(moves)
+            .map(|sq| -minimax(&pos.play(sq)))
+            .max()
+            .unwrap()
+    }
+
+    /// Every cell black except h8 empty and g8 white
+    fn last_empty() -> (u64, u64) {
+        let h8 = 1 << 63;
+        let g8 = 1 << 62;
+        (!(h8 | g8), g8)
+    }
+
+    #[test]
+    fn solves_the_last_move() {
+        let (black, white) = last_empty();
+        let s = solve(&Position::from_discs(black, white, Color::Black));
+        assert_eq!(s.best_move, Some(63));
+        assert_eq!(s.score, 64);
+    }
+
+    #[test]
+    fn solves_through_a_pass() {
+        let (black, white) = last_empty();
+        let s = solve(&Position::from_discs(black, white, Color::White));
+        assert_eq!(s.best_move, None);
+        assert_eq!(s.score, -64);
+    }
+
+    #[test]
+    fn agrees_with_minimax() {
+        // the lowest cell every ply down to 10 empties
+        let mut pos = Position::initial();
+        while pos.empties().count_ones() > 10 {
+            if pos.legal_moves() == 0 {
+                pos = pos.pass();
+                assert_ne!(pos.legal_moves(), 0, "game over too early");
+            }
+            pos = pos.play(pos.legal_moves().trailing_zeros());
+        }
+        let s = solve(&pos);
+        assert_eq!(s.score, minimax(&pos));
+        if let Some(m) = s.best_move {
+            assert_eq!(-minimax(&pos.play(m)), s.score);
+        }
+    }
+}
@@ -0,0 +1,464 @@
+//! Rules of the game on boards of any size
+//!
+//! `Game` owns the turn order: after each move the side to move is decided,
+//! passes are made automatically for a side without legal moves, and the game
+//! ends when neither side can move, a player resigns or runs out of time.
+//! Every change is reported as a `GameEvent` for scripts to follow.
+
+use crate::bitboard::{self, Color, Position};
+
+const DIRECTIONS: [(i64, i64); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EndReason {
+    /// Every cell is filled
+    BoardFull,
+    /// Neither side can move with empty cells left
+    NoMoves,
+    Resignation,
+    /// The flag of the loser fell
+    Timeout,
+}
+impl EndReason {
+    pub fn name(self) -> &'static str {
+        match self {
+            EndReason::BoardFull => "boardFull",
+            EndReason::NoMoves => "noMoves",
+            EndReason::Resignation => "resignation",
+            EndReason::Timeout => "timeout",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GameResult {
+    /// None for a draw
+    pub winner: Option<Color>,
+    pub black: u32,
+    pub white: u32,
+    pub reason: EndReason,
+}
+impl GameResult {
+    /// "black wins 40-24 (resignation)"
+    pub fn describe(&self) -> String {
+        match self.winner {
+            Some(c) => format!(
+                "{} wins {}-{} ({})",
+                c.name(),
+                self.black.max(self.white),
+                self.black.min(self.white),
+                self.reason.name()
+            ),
+            None => format!(
+                "draw {}-{} ({})",
+                self.black,
+                self.white,
+                self.reason.name()
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum GameEvent {
+    Placed {
+        color: Color,
+        cell: (u32, u32),
+        flipped: Vec<(u32, u32)>,
+    },
+    /// `color` had no legal moves
+    Passed {
+        color: Color,
+    },
+    /// `color` is to move with `legal_moves`
+    Turn {
+        color: Color,
+        legal_moves: Vec<(u32, u32)>,
+    },
+    Ended(GameResult),
+}
+
+#[derive(Clone)]
+pub struct Game {
+    board_size: usize,
+    /// Row-major
+    cells: Vec<Option<Color>>,
+    to_move: Color,
+    result: Option<GameResult>,
+}
+impl Game {
+    /// Four discs in the center, black to move
+    pub fn new(board_size: usize) -> Self {
+        let mut cells = vec![None; board_size * board_size];
+        let c = board_size / 2;
+        cells[(c - 1) + (c - 1) * board_size] = Some(Color::Black);
+        cells[c + c * board_size] = Some(Color::Black);
+        cells[c + (c - 1) * board_size] = Some(Color::White);
+        cells[(c - 1) + c * board_size] = Some(Color::White);
+        Game {
+            board_size,
+            cells,
+            to_move: Color::Black,
+            result: None,
+        }
+    }
+
+    /// An 8x8 game from an engine position
+    pub fn from_position(pos: &Position, to_move: Color) -> Self {
+        let (black, white) = pos.discs(to_move);
+        let mut cells = vec![None; bitboard::SIZE * bitboard::SIZE];
+        for sq in bitboard::squares(black) {
+            cells[sq as usize] = Some(Color::Black);
+        }
+        for sq in bitboard::squares(white) {
+            cells[sq as usize] = Some(Color::White);
+        }
+        Game {
+            board_size: bitboard::SIZE,
+            cells,
+            to_move,
+            result: None,
+        }
+    }
+
+    /// Settles the turn of a new game, which may have to pass or be over
+    pub fn begin(&mut self) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        self.settle_turn(&mut events);
+        events
+    }
+
+    /// Side to move, None when the game is over
+    pub fn to_move(&self) -> Option<Color> {
+        match self.result {
+            Some(_) => None,
+            None => Some(self.to_move),
+        }
+    }
+
+    pub fn result(&self) -> Option<&GameResult> {
+        self.result.as_ref()
+    }
+
+    /// Engine position with the side to move, or the side that was to move
+    /// when the game ended. None on boards other than 8x8.
+    pub fn engine_position(&self) -> Option<(Position, Color)> {
+        if self.board_size != bitboard::SIZE {
+            return None;
+        }
+        let (mut black, mut white) = (0, 0);
+        for (n, c) in self.cells.iter().enumerate() {
+            match c {
+                Some(Color::Black) => black |= 1 << n,
+                Some(Color::White) => white |= 1 << n,
+                None => (),
+            }
+        }
+        Some((
+            Position::from_discs(black, white, self.to_move),
+            self.to_move,
+        ))
+    }
+
+    /// Event describing the current state, `Turn` or `Ended`
+    pub fn state_event(&self) -> GameEvent {
+        match self.result {
+            Some(r) => GameEvent::Ended(r),
+            None => GameEvent::Turn {
+                color: self.to_move,
+                legal_moves: self.legal_moves(self.to_move),
+            },
+        }
+    }
+
+    fn cell(&self, x: i64, y: i64) -> Option<Option<Color>> {
+        let size = self.board_size as i64;
+        if 0 <= x && x < size && 0 <= y && y < size {
+            Some(self.cells[(x + y * size) as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Discs of the opponent flipped if `color` places at (x, y)
+    pub fn flips(&self, color: Color, x: u32, y: u32) -> Vec<(u32, u32)> {
+        let (x, y) = (x as i64, y as i64);
+        if self.cell(x, y) != Some(None) {
+            return Vec::new();
+        }
+        let mut flips = Vec::new();
+        for &(dx, dy) in &DIRECTIONS {
+            let mut line = Vec::new();
+            let (mut cx, mut cy) = (x + dx, y + dy);
+            while self.cell(cx, cy) == Some(Some(color.opponent())) {
+                line.push((cx as u32, cy as u32));
+                cx += dx;
+                cy += dy;
+            }
+            if self.cell(cx, cy) == Some(Some(color)) {
+                flips.extend(line);
+            }
+        }
+        flips
+    }
+
+    /// Cells where `color` can place, row-major
+    pub fn legal_moves(&self, color: Color) -> Vec<(u32, u32)> {
+        let size = self.board_size as u32;
+        (0..size * size)
+            .map(|n| (n % size, n / size))
+            .filter(|&(x, y)| !self.flips(color, x, y).is_empty())
+            .collect()
+    }
+
+    fn count(&self, color: Color) -> u32 {
+        self.cells.iter().filter(|&&c| c == Some(color)).count() as u32
+    }
+
+    /// Places a disc for the side to move
+    pub fn play(&mut self, x: u32, y: u32) -> Result<Vec<GameEvent>, String> {
+        if self.result.is_some() {
+            return Err("the game is over".into());
+        }
+        let color = self.to_move;
+        let flipped = self.flips(color, x, y);
+        if flipped.is_empty() {
+            return Err(format!(
+                "illegal move for {}: {}, {}",
+                color.name(),
+                x,
+                y
+            ));
+        }
+        let size = self.board_size as u32;
+        self.cells[(x + y * size) as usize] = Some(color);
+        for &(fx, fy) in &flipped {
+            self.cells[(fx + fy * size) as usize] = Some(color);
+        }
+
+        let mut events = vec![GameEvent::Placed {
+            color,
+            cell: (x, y),
+            flipped,
+        }];
+        self.to_move = color.opponent();
+        self.settle_turn(&mut events);
+        Ok(events)
+    }
+
+    pub fn resign(&mut self, color: Color) -> Result<Vec<GameEvent>, String> {
+        self.lose(color, EndReason::Resignation)
+    }
+
+    /// Ends the game as a loss of `color` on time
+    pub fn flag_fall(
+        &mut self,
+        color: Color,
+    ) -> Result<Vec<GameEvent>, String> {
+        self.lose(color, EndReason::Timeout)
+    }
+
+    fn lose(
+        &mut self,
+        color: Color,
+        reason: EndReason,
+    ) -> Result<Vec<GameEvent>, String> {
+        if self.result.is_some() {
+            return Err("the game is over".into());
+        }
+        let result = GameResult {
+            winner: Some(color.opponent()),
+            black: self.count(Color::Black),
+            white: self.count(Color::White),
+            reason,
+        };
+        self.result = Some(result);
+        Ok(vec![GameEvent::Ended(result)])
+    }
+
+    /// Passes if only the opponent can move, or ends the game when neither
+    /// can. Passes are not made in an ended game.
+    fn settle_turn(&mut self, events: &mut Vec<GameEvent>) {
+        let legal_moves = self.legal_moves(self.to_move);
+        if !legal_moves.is_empty() {
+            events.push(GameEvent::Turn {
+                color: self.to_move,
+                legal_moves,
+            });
+            return;
+        }
+        let opponent = self.to_move.opponent();
+        let legal_moves = self.legal_moves(opponent);
+        if !legal_moves.is_empty() {
+            events.push(GameEvent::Passed {
+                color: self.to_move,
+            });
+            self.to_move = opponent;
+            events.push(GameEvent::Turn {
+                color: opponent,
+                legal_moves,
+            });
+            return;
+        }
+
+        let full = self.cells.iter().all(Option::is_some);
+        let (black, white) =
+            (self.count(Color::Black), self.count(Color::White));
+        let result = GameResult {
+            winner: match black.cmp(&white) {
+                std::cmp::Ordering::Greater => Some(Color::Black),
+                std::cmp::Ordering::Less => Some(Color::White),
+                std::cmp::Ordering::Equal => None,
+            },
+            black,
+            white,
+            reason: if full {
+                EndReason::BoardFull
+            } else {
+                EndReason::NoMoves
+            },
+        };
+        self.result = Some(result);
+        events.push(GameEvent::Ended(result));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rows of `B`, `W` and `_`
+    fn game(rows: &[&str], to_move: Color) -> Game {
+        let cells = rows
+            .iter()
+            .flat_map(|r| r.chars())
+            .map(|c| match c {
+                'B' => Some(Color::Black),
+                'W' => Some(Color::White),
+                _ => None,
+            })
+            .collect();
+        Game {
+            board_size: rows.len(),
+            cells,
+            to_move,
+            result: None,
+        }
+    }
+
+    fn ended(events: &[GameEvent]) -> GameResult {
+        match events {
+            [GameEvent::Ended(r)] => *r,
+            _ => panic!("not just an end: {:?}", events),
+        }
+    }
+
+    #[test]
+    fn plays_on_6x6() {
+        let mut g = Game::new(6);
+        match g.begin().as_slice() {
+            [GameEvent::Turn { color, legal_moves }] => {
+                assert_eq!(*color, Color::Black);
+                assert_eq!(legal_moves, &[(3, 1), (4, 2), (1, 3), (2, 4)]);
+            }
+            e => panic!("unexpected events: {:?}", e),
+        }
+        assert!(g.play(0, 0).is_err());
+        let events = g.play(3, 1).unwrap();
+        match &events[..] {
+            [GameEvent::Placed { color, flipped, .. }, turn] => {
+                assert_eq!(*color, Color::Black);
+                assert_eq!(flipped, &[(3, 2)]);
+                assert!(matches!(
+                    turn,
+                    GameEvent::Turn {
+                        color: Color::White,
+                        ..
+                    }
+                ));
+            }
+            e => panic!("unexpected events: {:?}", e),
+        }
+        assert_eq!(g.to_move(), Some(Color::White));
+    }
+
+    #[test]
+    fn passes_when_only_the_opponent_can_move() {
+        let mut g = game(
+            &["WWB___", "______", "______", "______", "______", "______"],
+            Color::Black,
+        );
+        let events = g.begin();
+        match &events[..] {
+            [GameEvent::Passed { color }, turn] => {
+                assert_eq!(*color, Color::Black);
+                let legal_moves = match turn {
+                    GameEvent::Turn { color, legal_moves } => {
+                        assert_eq!(*color, Color::White);
+                        legal_moves
+                    }
+                    e => panic!("no turn: {:?}", e),
+                };
+                assert_eq!(legal_moves, &[(3, 0)]);
+            }
+            e => panic!("unexpected events: {:?}", e),
+        }
+        assert_eq!(g.to_move(), Some(Color::White));
+    }
+
+    #[test]
+    fn ends_without_passes_when_neither_can_move() {
+        let mut g = game(
+            &["BB____", "______", "______", "______", "______", "______"],
+            Color::White,
+        );
+        let r = ended(&g.begin());
+        assert_eq!(r.winner, Some(Color::Black));
+        assert_eq!((r.black, r.white), (2, 0));
+        assert_eq!(r.reason, EndReason::NoMoves);
+        assert_eq!(g.to_move(), None);
+    }
+
+    #[test]
+    fn ends_on_a_full_board() {
+        let mut g = game(
+            &["BBBBBB", "BBBBBB", "BBBBBB", "BBWWWW", "WWWWWW", "WWWWWW"],
+            Color::Black,
+        );
+        let r = ended(&g.begin());
+        assert_eq!(r.winner, Some(Color::Black));
+        assert_eq!((r.black, r.white), (20, 16));
+        assert_eq!(r.reason, EndReason::BoardFull);
+    }
+
+    #[test]
+    fn resign_and_flag_fall() {
+        let mut g = Game::new(6);
+        g.begin();
+        let r = ended(&g.resign(Color::Black).unwrap());
+        assert_eq!(r.winner, Some(Color::White));
+        assert_eq!(r.reason, EndReason::Resignation);
+        assert_eq!(g.play(3, 1).err().unwrap(), "the game is over");
+        assert_eq!(g.resign(Color::White).err().unwrap(), "the game is over");
+
+        let mut g = Game::from_position(&Position::initial(), Color::Black);
+        g.begin();
+        let r = ended(&g.flag_fall(Color::White).unwrap());
+        assert_eq!(r.winner, Some(Color::Black));
+        assert_eq!((r.black, r.white), (2, 2));
+        assert_eq!(r.reason, EndReason::Timeout);
+        assert_eq!(
+            g.flag_fall(Color::Black).err().unwrap(),
+            "the game is over"
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! KHR_debug message callback routed to the logger
+//!
+//! The callback must not call GL functions, so labels and bindings of
+//! objects are tracked on the Rust side by `glres` and `shader`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+    static OBJECT_LABELS: RefCell<HashMap<(gl::types::GLenum, gl::types::GLuint), String>> =
+        RefCell::new(HashMap::new());
+    /// Currently bound object for each object namespace
+    static BOUND_OBJECTS: RefCell<HashMap<gl::types::GLenum, gl::types::GLuint>> =
+        RefCell::new(HashMap::new());
+    static FATAL_MESSAGE: RefCell<Option<String>> = RefCell::new(None);
+}
+
+pub fn record_label(
+    identifier: gl::types::GLenum,
+    handle: gl::types::GLuint,
+    label: &str,
+) {
+    OBJECT_LABELS.with(|l| {
+        l.borrow_mut()
+            .insert((identifier, handle), label.to_owned())
+    });
+}
+/// Forgets the label of a deleted object (handles may be reused)
+pub fn forget_object(identifier: gl::types::GLenum, handle: gl::types::GLuint) {
+    OBJECT_LABELS.with(|l| l.borrow_mut().remove(&(identifier, handle)));
+}
+pub fn record_binding(
+    identifier: gl::types::GLenum,
+    handle: gl::types::GLuint,
+) {
+    BOUND_OBJECTS.with(|b| b.borrow_mut().insert(identifier, handle));
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Notification,
+    Low,
+    Medium,
+    High,
+}
+impl Severity {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "notification" => Some(Severity::Notification),
+            "low" => Some(Severity::Low),
+            "medium" => Some(Severity::Medium),
+            "high" => Some(Severity::High),
+            _ => None,
+        }
+    }
+
+    fn from_gl(severity: gl::types::GLenum) -> Self {
+        match severity {
+            gl::DEBUG_SEVERITY_HIGH => Severity::High,
+            gl::DEBUG_SEVERITY_MEDIUM => Severity::Medium,
+            gl::DEBUG_SEVERITY_LOW => Severity::Low,
+            _ => Severity::Notification,
+        }
+    }
+    fn to_gl(self) -> gl::types::GLenum {
+        match self {
+            Severity::High => gl::DEBUG_SEVERITY_HIGH,
+            Severity::Medium => gl::DEBUG_SEVERITY_MEDIUM,
+            Severity::Low => gl::DEBUG_SEVERITY_LOW,
+            Severity::Notification => gl::DEBUG_SEVERITY_NOTIFICATION,
+        }
+    }
+    fn log_level(self) -> log::Level {
+        match self {
+            Severity::High => log::Level::Error,
+            Severity::Medium => log::Level::Warn,
+            Severity::Low => log::Level::Info,
+            Severity::Notification => log::Level::Debug,
+        }
+    }
+}
+
+pub struct DebugOutputConfig {
+    pub min_severity: Severity,
+    /// Panics (at the next `check`) on high severity errors
+    pub panic_on_error: bool,
+}
+
+fn source_name(source: gl::types::GLenum) -> &'static str {
+    match source {
+        gl::DEBUG_SOURCE_API => "API",
+        gl::DEBUG_SOURCE_WINDOW_SYSTEM => "Window System",
+        gl::DEBUG_SOURCE_SHADER_COMPILER => "Shader Compiler",
+        gl::DEBUG_SOURCE_THIRD_PARTY => "Third Party",
+        gl::DEBUG_SOURCE_APPLICATION => "Application",
+        _ => "Other",
+    }
+}
+fn type_name(ty: gl::types::GLenum) -> &'static str {
+    match ty {
+        gl::DEBUG_TYPE_ERROR => "Error",
+        gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "Deprecated Behavior",
+        gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "Undefined Behavior",
+        gl::DEBUG_TYPE_PORTABILITY => "Portability",
+        gl::DEBUG_TYPE_PERFORMANCE => "Performance",
+        gl::DEBUG_TYPE_MARKER => "Marker",
+        _ => "Other",
+    }
+}
+
+/// Describes currently bound objects with their labels
+fn bound_objects_description() -> String {
+    const TRACKED: &'static [(gl::types::GLenum, &'static str)] = &[
+        (gl::PROGRAM, "program"),
+        (gl::VERTEX_ARRAY, "vertex array"),
+        (gl::FRAMEBUFFER, "framebuffer"),
+    ];
+
+    BOUND_OBJECTS.with(|b| {
+        let b = b.borrow();
+        OBJECT_LABELS.with(|l| {
+            let l = l.borrow();
+            TRACKED
+                .iter()
+                .filter_map(|&(id, name)| {
+                    let handle = *b.get(&id).filter(|&&h| h != 0)?;
+                    Some(match l.get(&(id, handle)) {
+                        Some(label) => format!("{} {:?}", name, label),
+                        None => format!("{} #{}", name, handle),
+                    })
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+    })
+}
+
+extern "system" fn debug_message_callback(
+    source: gl::types::GLenum,
+    ty: gl::types::GLenum,
+    id: gl::types::GLuint,
+    severity: gl::types::GLenum,
+    length: gl::types::GLsizei,
+    message: *const gl::types::GLchar,
+    user_param: *mut std::ffi::c_void,
+) {
+    let config = unsafe { &*(user_param as *const DebugOutputConfig) };
+    let severity = Severity::from_gl(severity);
+    if severity < config.min_severity {
+        return;
+    }
+
+    let message = unsafe {
+        String::from_utf8_lossy(std::slice::from_raw_parts(
+            message as *const u8,
+            length as usize,
+        ))
+    };
+    let text = format!(
+        "[GL {} {} #{}] {} (bound: {})",
+        source_name(source),
+        type_name(ty),
+        id,
+        message.trim_end(),
+        bound_objects_description()
+    );
+    log::log!(severity.log_level(), "{}", text);
+
+    // unwinding out of the callback is not allowed: panic later in check()
+    if config.panic_on_error
+        && severity == Severity::High
+        && ty == gl::DEBUG_TYPE_ERROR
+    {
+        FATAL_MESSAGE.with(|m| {
+            m.borrow_mut().get_or_insert(text);
+        });
+    }
+}
+
+/// Installs the debug message callback. Requires a debug context.
+/// Returns false if KHR_debug is not available.
+pub fn install(config: DebugOutputConfig) -> bool {
+    if !gl::DebugMessageCallback::is_loaded()
+        || !gl::DebugMessageControl::is_loaded()
+    {
+        return false;
+    }
+
+    let min_severity = config.min_severity;
+    // lives as long as the context
+    let config = Box::leak(Box::new(config));
+    unsafe {
+        gl::Enable(gl::DEBUG_OUTPUT);
+        // keeps bound object tracking valid while the callback runs
+        gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+        gl::DebugMessageCallback(
+            Some(debug_message_callback),
+            config as *const DebugOutputConfig as _,
+        );
+        for &s in &[
+            Severity::Notification,
+            Severity::Low,
+            Severity::Medium,
+            Severity::High,
+        ] {
+            gl::DebugMessageControl(
+                gl::DONT_CARE,
+                gl::DONT_CARE,
+                s.to_gl(),
+                0,
+                std::ptr::null(),
+                if s >= min_severity {
+                    gl::TRUE
+                } else {
+                    gl::FALSE
+                },
+            );
+        }
+    }
+
+    true
+}
+
+/// Panics if a high severity error has been reported in panic mode
+pub fn check() {
+    if let Some(m) = FATAL_MESSAGE.with(|m| m.borrow_mut().take()) {
+        panic!("GL error reported: {}", m);
+    }
+}
@@ -0,0 +1,374 @@
+//! Owned GL objects, deleted with the matching `glDelete*` on drop
+
+use std::ffi::c_void;
+use std::marker::PhantomData;
+
+use crate::gl_debug;
+
+/// GL objects which can be named through `glObjectLabel`
+pub trait GlObject {
+    /// Object namespace passed to `glObjectLabel`
+    const IDENTIFIER: gl::types::GLenum;
+
+    fn handle(&self) -> gl::types::GLuint;
+
+    /// Names the object for debugging tools.
+    /// Does nothing if KHR_debug is not available.
+    ///
+    /// Objects created by `glGen*` must have been bound at least once.
+    fn set_label(&self, label: &str) {
+        gl_debug::record_label(Self::IDENTIFIER, self.handle(), label);
+        if !gl::ObjectLabel::is_loaded() {
+            return;
+        }
+
+        unsafe {
+            gl::ObjectLabel(
+                Self::IDENTIFIER,
+                self.handle(),
+                label.len() as _,
+                label.as_ptr() as _,
+            );
+        }
+    }
+}
+
+pub trait BufferTarget {
+    const TARGET: gl::types::GLenum;
+}
+pub enum ArrayBufferTarget {}
+impl BufferTarget for ArrayBufferTarget {
+    const TARGET: gl::types::GLenum = gl::ARRAY_BUFFER;
+}
+pub enum ElementArrayBufferTarget {}
+impl BufferTarget for ElementArrayBufferTarget {
+    const TARGET: gl::types::GLenum = gl::ELEMENT_ARRAY_BUFFER;
+}
+pub enum UniformBufferTarget {}
+impl BufferTarget for UniformBufferTarget {
+    const TARGET: gl::types::GLenum = gl::UNIFORM_BUFFER;
+}
+
+pub struct Buffer<T: BufferTarget>(gl::types::GLuint, PhantomData<T>);
+impl<T: BufferTarget> Buffer<T> {
+    pub fn new() -> Self {
+        let mut handle = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut handle);
+        }
+        Buffer(handle, PhantomData)
+    }
+}
+impl Buffer<UniformBufferTarget> {
+    /// Binds the buffer to an indexed uniform block binding
+    pub fn bind_base(&self, index: gl::types::GLuint) {
+        unsafe {
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, index, self.0);
+        }
+    }
+}
+impl<T: BufferTarget> GlObject for Buffer<T> {
+    const IDENTIFIER: gl::types::GLenum = gl::BUFFER;
+
+    fn handle(&self) -> gl::types::GLuint {
+        self.0
+    }
+}
+impl<T: BufferTarget> Drop for Buffer<T> {
+    fn drop(&mut self) {
+        gl_debug::forget_object(Self::IDENTIFIER, self.0);
+        unsafe {
+            gl::DeleteBuffers(1, &self.0);
+        }
+    }
+}
+
+pub struct BufferBindPoint<T: BufferTarget>(PhantomData<T>);
+impl<T: BufferTarget> BufferBindPoint<T> {
+    pub fn bind(&self, buf: &Buffer<T>) -> &Self {
+        unsafe {
+            gl::BindBuffer(T::TARGET, buf.0);
+        }
+        self
+    }
+    pub fn unbind(&self) -> &Self {
+        unsafe {
+            gl::BindBuffer(T::TARGET, 0);
+        }
+        self
+    }
+    pub fn data<E>(&self, slice: &[E], usage: gl::types::GLenum) -> &Self {
+        unsafe {
+            gl::BufferData(
+                T::TARGET,
+                (std::mem::size_of::<E>() * slice.len()) as _,
+                slice.as_ptr() as _,
+                usage,
+            );
+        }
+        self
+    }
+    pub fn subdata_ptr(
+        &self,
+        ptr: *mut c_void,
+        size: gl::types::GLsizeiptr,
+        offset: gl::types::GLintptr,
+    ) -> &Self {
+        unsafe {
+            gl::BufferSubData(T::TARGET, offset, size, ptr);
+        }
+        self
+    }
+}
+pub static ARRAY_BUFFER: BufferBindPoint<ArrayBufferTarget> =
+    BufferBindPoint(PhantomData);
+pub static ELEMENT_ARRAY_BUFFER: BufferBindPoint<ElementArrayBufferTarget> =
+    BufferBindPoint(PhantomData);
+pub static UNIFORM_BUFFER: BufferBindPoint<UniformBufferTarget> =
+    BufferBindPoint(PhantomData);
+
+pub struct VertexArray(gl::types::GLuint);
+impl VertexArray {
+    pub fn new() -> Self {
+        let mut handle = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut handle);
+        }
+        VertexArray(handle)
+    }
+
+    pub fn bind(&self) {
+        gl_debug::record_binding(gl::VERTEX_ARRAY, self.0);
+        unsafe {
+            gl::BindVertexArray(self.0);
+        }
+    }
+    pub fn unbind() {
+        gl_debug::record_binding(gl::VERTEX_ARRAY, 0);
+        unsafe {
+            gl::BindVertexArray(0);
+        }
+    }
+}
+impl GlObject for VertexArray {
+    const IDENTIFIER: gl::types::GLenum = gl::VERTEX_ARRAY;
+
+    fn handle(&self) -> gl::types::GLuint {
+        self.0
+    }
+}
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        gl_debug::forget_object(Self::IDENTIFIER, self.0);
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.0);
+        }
+    }
+}
+
+/// 2D texture
+pub struct Texture(gl::types::GLuint);
+impl Texture {
+    pub fn new() -> Self {
+        let mut handle = 0;
+        unsafe {
+            gl::GenTextures(1, &mut handle);
+        }
+        Texture(handle)
+    }
+
+    pub fn bind(&self, unit: gl::types::GLuint) -> &Self {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_2D, self.0);
+        }
+        self
+    }
+
+    /// Uploads tightly packed RGBA8 pixels (the texture must be bound)
+    pub fn image_rgba8(&self, width: i32, height: i32, pixels: &[u8]) -> &Self {
+        assert_eq!(pixels.len(), (width * height * 4) as usize);
+        unsafe {
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA8 as _,
+                width,
+                height,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_ptr() as _,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MIN_FILTER,
+                gl::LINEAR as _,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MAG_FILTER,
+                gl::LINEAR as _,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_S,
+                gl::CLAMP_TO_EDGE as _,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_T,
+                gl::CLAMP_TO_EDGE as _,
+            );
+        }
+        self
+    }
+}
+impl GlObject for Texture {
+    const IDENTIFIER: gl::types::GLenum = gl::TEXTURE;
+
+    fn handle(&self) -> gl::types::GLuint {
+        self.0
+    }
+}
+impl Drop for Texture {
+    fn drop(&mut self) {
+        gl_debug::forget_object(Self::IDENTIFIER, self.0);
+        unsafe {
+            gl::DeleteTextures(1, &self.0);
+        }
+    }
+}
+
+pub struct Renderbuffer(gl::types::GLuint);
+impl Renderbuffer {
+    pub fn new_multisample(
+        format: gl::types::GLenum,
+        width: i32,
+        height: i32,
+        samples: u32,
+    ) -> Self {
+        let mut handle = 0;
+        unsafe {
+            gl::GenRenderbuffers(1, &mut handle);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, handle);
+            gl::RenderbufferStorageMultisample(
+                gl::RENDERBUFFER,
+                samples as _,
+                format,
+                width,
+                height,
+            );
+            gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+        }
+        Renderbuffer(handle)
+    }
+}
+impl GlObject for Renderbuffer {
+    const IDENTIFIER: gl::types::GLenum = gl::RENDERBUFFER;
+
+    fn handle(&self) -> gl::types::GLuint {
+        self.0
+    }
+}
+impl Drop for Renderbuffer {
+    fn drop(&mut self) {
+        gl_debug::forget_object(Self::IDENTIFIER, self.0);
+        unsafe {
+            gl::DeleteRenderbuffers(1, &self.0);
+        }
+    }
+}
+
+pub struct Framebuffer(gl::types::GLuint);
+impl Framebuffer {
+    pub fn new() -> Self {
+        let mut handle = 0;
+        unsafe {
+            gl::GenFramebuffers(1, &mut handle);
+        }
+        Framebuffer(handle)
+    }
+
+    pub fn bind(&self, target: gl::types::GLenum) -> &Self {
+        if target != gl::READ_FRAMEBUFFER {
+            gl_debug::record_binding(gl::FRAMEBUFFER, self.0);
+        }
+        unsafe {
+            gl::BindFramebuffer(target, self.0);
+        }
+        self
+    }
+    /// Binds the window's default framebuffer
+    pub fn bind_default(target: gl::types::GLenum) {
+        if target != gl::READ_FRAMEBUFFER {
+            gl_debug::record_binding(gl::FRAMEBUFFER, 0);
+        }
+        unsafe {
+            gl::BindFramebuffer(target, 0);
+        }
+    }
+
+    /// Attaches a renderbuffer (the framebuffer must be bound to
+    /// GL_FRAMEBUFFER)
+    pub fn attach_renderbuffer(
+        &self,
+        attachment: gl::types::GLenum,
+        rb: &Renderbuffer,
+    ) -> &Self {
+        unsafe {
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                attachment,
+                gl::RENDERBUFFER,
+                rb.0,
+            );
+        }
+        self
+    }
+    /// Attaches a 2D texture (the framebuffer must be bound to
+    /// GL_FRAMEBUFFER)
+    #[allow(dead_code)]
+    pub fn attach_texture(
+        &self,
+        attachment: gl::types::GLenum,
+        tex: &Texture,
+    ) -> &Self {
+        unsafe {
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                attachment,
+                gl::TEXTURE_2D,
+                tex.0,
+                0,
+            );
+        }
+        self
+    }
+
+    /// Checks completeness (the framebuffer must be bound to GL_FRAMEBUFFER)
+    pub fn status(&self) -> Result<(), gl::types::GLenum> {
+        let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        if status == gl::FRAMEBUFFER_COMPLETE {
+            Ok(())
+        } else {
+            Err(status)
+        }
+    }
+}
+impl GlObject for Framebuffer {
+    const IDENTIFIER: gl::types::GLenum = gl::FRAMEBUFFER;
+
+    fn handle(&self) -> gl::types::GLuint {
+        self.0
+    }
+}
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        gl_debug::forget_object(Self::IDENTIFIER, self.0);
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.0);
+        }
+    }
+}
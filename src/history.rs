@@ -0,0 +1,137 @@
+//! Moves played in the current game, with undo and redo
+//!
+//! The game records every move and pass as it is played. Undone moves are
+//! kept for redo until a different move is recorded.
+
+use crate::bitboard::Color;
+
+/// Requested by key bindings, handled by scripts
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HistoryCommand {
+    Undo,
+    Redo,
+}
+impl HistoryCommand {
+    pub fn name(self) -> &'static str {
+        match self {
+            HistoryCommand::Undo => "undo",
+            HistoryCommand::Redo => "redo",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub color: Color,
+    /// (x, y) of the placed disc, None for a pass
+    pub cell: Option<(u32, u32)>,
+    /// (x, y) of the discs flipped by the move
+    pub flipped: Vec<(u32, u32)>,
+    /// Script time when the move was recorded
+    pub time_ms: f64,
+}
+
+pub struct MoveHistory {
+    entries: Vec<HistoryEntry>,
+    /// Number of entries played, the rest can be redone
+    cursor: usize,
+}
+impl MoveHistory {
+    pub fn new() -> Self {
+        MoveHistory {
+            entries: Vec::new(),
+            cursor: 0,
+        }
+    }
+
+    /// Appends a played move, discarding undone ones
+    pub fn record(&mut self, entry: HistoryEntry) {
+        self.entries.truncate(self.cursor);
+        self.entries.push(entry);
+        self.cursor += 1;
+    }
+
+    /// The last played move, now to be taken back
+    pub fn undo(&mut self) -> Option<&HistoryEntry> {
+        self.cursor = self.cursor.checked_sub(1)?;
+        Some(&self.entries[self.cursor])
+    }
+
+    /// The last undone move, now to be played again
+    pub fn redo(&mut self) -> Option<&HistoryEntry> {
+        let entry = self.entries.get(self.cursor)?;
+        self.cursor += 1;
+        Some(entry)
+    }
+
+    /// Moves played so far, oldest first
+    pub fn played(&self) -> &[HistoryEntry] {
+        &self.entries[..self.cursor]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(color: Color, x: u32) -> HistoryEntry {
+        HistoryEntry {
+            color,
+            cell: Some((x, 0)),
+            flipped: Vec::new(),
+            time_ms: 0.0,
+        }
+    }
+
+    fn cells(history: &MoveHistory) -> Vec<Option<(u32, u32)>> {
+        history.played().iter().map(|e| e.cell).collect()
+    }
+
+    #[test]
+    fn undo_and_redo_move_the_cursor() {
+        let mut history = MoveHistory::new();
+        history.record(entry(Color::Black, 1));
+        history.record(entry(Color::White, 2));
+        assert_eq!(cells(&history), vec![Some((1, 0)), Some((2, 0))]);
+
+        assert_eq!(history.undo().unwrap().cell, Some((2, 0)));
+        assert_eq!(cells(&history), vec![Some((1, 0))]);
+        assert_eq!(history.undo().unwrap().color, Color::Black);
+        assert!(history.played().is_empty());
+
+        assert_eq!(history.redo().unwrap().cell, Some((1, 0)));
+        assert_eq!(cells(&history), vec![Some((1, 0))]);
+    }
+
+    #[test]
+    fn undo_and_redo_stop_at_the_ends() {
+        let mut history = MoveHistory::new();
+        assert!(history.undo().is_none());
+        assert!(history.redo().is_none());
+
+        history.record(entry(Color::Black, 1));
+        assert!(history.redo().is_none());
+        assert_eq!(history.played().len(), 1);
+        assert!(history.undo().is_some());
+        assert!(history.undo().is_none());
+        assert!(history.played().is_empty());
+        // the cursor did not move past the start
+        assert_eq!(history.redo().unwrap().cell, Some((1, 0)));
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn recording_after_undo_drops_redo() {
+        let mut history = MoveHistory::new();
+        history.record(entry(Color::Black, 1));
+        history.record(entry(Color::White, 2));
+        history.record(entry(Color::Black, 3));
+        history.undo();
+        history.undo();
+
+        history.record(entry(Color::White, 4));
+        assert_eq!(cells(&history), vec![Some((1, 0)), Some((4, 0))]);
+        assert!(history.redo().is_none());
+        assert_eq!(history.undo().unwrap().cell, Some((4, 0)));
+    }
+}
@@ -1,8 +1,62 @@
-use std::{convert::TryFrom, ffi::c_void};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::Arc;
 
 use glfw::Context;
 use rusty_v8 as v8;
 
+mod analysis;
+mod asset_watcher;
+mod audio;
+mod bitboard;
+mod book;
+mod cell_state;
+mod clock;
+mod draw2d;
+mod endgame;
+mod game;
+mod gl_debug;
+mod glres;
+mod history;
+mod options;
+mod particles;
+mod position_codec;
+mod record;
+mod render_target;
+mod search;
+mod shader;
+mod std140;
+mod theme;
+mod tween;
+use self::analysis::{Analyzer, Evaluation};
+use self::asset_watcher::AssetWatcher;
+use self::audio::{Audio, PlayOptions};
+use self::bitboard::{Color, Position};
+use self::book::Book;
+use self::cell_state::{
+    cell_index, read_state_flags, CellState, BOARD_CELL_COUNT,
+};
+use self::clock::{GameClock, TimeControl};
+use self::draw2d::{DrawList, Renderer2D};
+use self::game::{Game, GameEvent};
+use self::glres::{
+    ArrayBufferTarget, Buffer, ElementArrayBufferTarget, GlObject,
+    UniformBufferTarget, VertexArray, ARRAY_BUFFER, ELEMENT_ARRAY_BUFFER,
+    UNIFORM_BUFFER,
+};
+use self::history::{HistoryCommand, HistoryEntry, MoveHistory};
+use self::options::{AppOptions, Command};
+use self::particles::ParticleSystem;
+use self::position_codec::PositionFormat;
+use self::record::GameRecord;
+use self::render_target::{MultisampleFramebuffer, RenderTarget};
+use self::search::{SearchKind, SearchRequest, SearchWorker};
+use self::shader::{Program, Shader, TextureUnit};
+use self::theme::{Theme, Themes};
+use self::tween::{
+    cell_float_field, AnimationOptions, Easing, Tween, TweenScheduler,
+};
+
 /*
 stone movement
 
@@ -20,9 +74,29 @@ f(x) = -4x^2 + 4x = 4x(1 - x)
 */
 
 fn main() {
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or("info"),
+    )
+    .init();
+    let options = AppOptions::from_args();
+    match &options.command {
+        Some(Command::Solve { position, to_move }) => {
+            return solve_command(position, *to_move)
+        }
+        Some(Command::BuildBook {
+            transcripts,
+            output,
+        }) => return build_book_command(transcripts, output),
+        Some(Command::ShowPosition { position, to_move }) => {
+            return show_position_command(position, *to_move)
+        }
+        None => (),
+    }
     let mut glfw =
         glfw::init(glfw::FAIL_ON_ERRORS).expect("Failed to initialize glfw");
     glfw.window_hint(glfw::WindowHint::Resizable(false));
+    glfw.window_hint(glfw::WindowHint::Samples(Some(options.window_samples())));
+    glfw.window_hint(glfw::WindowHint::OpenGlDebugContext(options.gl_debug));
     if cfg!(target_os = "macos") {
         glfw.window_hint(glfw::WindowHint::ContextVersionMajor(3));
         glfw.window_hint(glfw::WindowHint::ContextVersionMinor(2));
@@ -36,6 +110,8 @@ fn main() {
         .expect("Failed to create window");
     window.set_mouse_button_polling(true);
     window.set_cursor_pos_polling(true);
+    window.set_key_polling(true);
+    window.set_drag_and_drop_polling(true);
     window.make_current();
     gl::load_with(|s| glfw.get_proc_address_raw(s));
     println!("Using OpenGL {}", unsafe {
@@ -43,15 +119,75 @@ fn main() {
             .to_str()
             .expect("invalid string")
     });
-    let buffers = Buffers::new();
-    let shaders = Shaders::new();
-    let mut se = ScriptEngine::new();
+    if options.gl_debug {
+        let installed = gl_debug::install(gl_debug::DebugOutputConfig {
+            min_severity: options.gl_debug_severity,
+            panic_on_error: options.gl_debug_panic,
+        });
+        if !installed {
+            log::warn!("KHR_debug is not available, GL debug output disabled");
+        }
+    }
+    let (fb_width, fb_height) = window.get_framebuffer_size();
+    // 2D draw list coordinates are in window pixels like the cursor position
+    let (window_width, window_height) = window.get_size();
+    let render_target = if options.offscreen_msaa && options.msaa_samples > 0 {
+        RenderTarget::OffscreenMultisample(MultisampleFramebuffer::new(
+            fb_width,
+            fb_height,
+            options.msaa_samples,
+        ))
+    } else {
+        RenderTarget::Default {
+            multisample: options.msaa_samples > 0,
+            width: fb_width,
+            height: fb_height,
+        }
+    };
+    let buffers = Buffers::new(options.board_size);
+    let mut shaders = Shaders::new();
+    let mut particles = ParticleSystem::new(
+        particles::load_presets(particles::PRESETS_PATH)
+            .unwrap_or_else(|e| panic!("{}", e)),
+        options.board_size,
+    );
+    let mut renderer_2d = Renderer2D::new();
+    let mut themes = Themes::new(options.theme.as_deref());
+    let mut audio = Audio::new(&options.audio_output);
+    let mut asset_watcher = AssetWatcher::new("./assets");
+    let book = match Book::load(&options.book_path) {
+        Ok(b) => Some(Arc::new(b)),
+        Err(e) => {
+            log::warn!("{}", e);
+            None
+        }
+    };
+    if options.ai_color.is_some() && options.board_size != bitboard::SIZE {
+        panic!("--ai requires an 8x8 board");
+    }
+    let search_worker = SearchWorker::new(book.clone());
+    let mut analyzer = Analyzer::new();
+    let mut se = ScriptEngine::new(options.board_size, options.ai_color, book);
+    if let Some((position, to_move)) = options.start_position {
+        if options.board_size != bitboard::SIZE {
+            panic!("--position requires an 8x8 board");
+        }
+        se.set_start_position(position, to_move);
+    }
+    if let Some(control) = options.time_control {
+        se.set_time_control(control, options.clock_pauses_for_animations);
+    }
+    if let Some(path) = &options.load_path {
+        se.load_game(path).unwrap_or_else(|e| panic!("{}", e));
+    }
     let code = std::fs::read_to_string("./scripts/index.js")
         .expect("Failed to load script");
     se.execute_code(&code);
 
     let timer = std::time::Instant::now();
+    let mut last_elapsed = std::time::Duration::default();
     while !window.should_close() {
+        let mut screenshot_requested = false;
         glfw.poll_events();
         for (_, e) in glfw::flush_messages(&events) {
             match e {
@@ -72,36 +208,205 @@ fn main() {
                 glfw::WindowEvent::CursorPos(x, y) => {
                     se.set_cursor_pos(x, y);
                 }
+                glfw::WindowEvent::Key(
+                    glfw::Key::F12,
+                    _,
+                    glfw::Action::Press,
+                    _,
+                ) => {
+                    screenshot_requested = true;
+                }
+                glfw::WindowEvent::Key(
+                    glfw::Key::T,
+                    _,
+                    glfw::Action::Press,
+                    _,
+                ) => {
+                    themes.cycle();
+                }
+                glfw::WindowEvent::Key(
+                    glfw::Key::N,
+                    _,
+                    glfw::Action::Press,
+                    _,
+                ) => {
+                    se.request_new_game();
+                }
+                glfw::WindowEvent::Key(
+                    glfw::Key::Z,
+                    _,
+                    glfw::Action::Press,
+                    mods,
+                ) if mods.contains(glfw::Modifiers::Control) => {
+                    se.request_history_command(
+                        if mods.contains(glfw::Modifiers::Shift) {
+                            HistoryCommand::Redo
+                        } else {
+                            HistoryCommand::Undo
+                        },
+                    );
+                }
+                glfw::WindowEvent::Key(
+                    glfw::Key::Y,
+                    _,
+                    glfw::Action::Press,
+                    mods,
+                ) if mods.contains(glfw::Modifiers::Control) => {
+                    se.request_history_command(HistoryCommand::Redo);
+                }
+                glfw::WindowEvent::FileDrop(paths) => {
+                    // the last one wins if several files are dropped
+                    for p in paths {
+                        if let Err(e) = se.load_game(&p.to_string_lossy()) {
+                            log::warn!("{}", e);
+                        }
+                    }
+                }
                 _ => {}
             }
         }
 
+        if asset_watcher.poll() {
+            shaders.reload();
+            match particles::load_presets(particles::PRESETS_PATH) {
+                Ok(p) => particles.set_presets(p),
+                Err(e) => log::warn!("{}", e),
+            }
+            themes.reload_file();
+        }
+
         let elapsed = timer.elapsed();
         se.set_current_time(elapsed);
+        if se.take_new_game_request() {
+            se.new_session(&code);
+            buffers.reset_board_state();
+        }
 
+        for (request, result) in search_worker.poll() {
+            let value = match request.kind {
+                SearchKind::BestMove(_) => ScriptValue::cell(result.best_move),
+                SearchKind::Solve => ScriptValue::Object(vec![
+                    ("move", ScriptValue::cell(result.best_move)),
+                    ("score", ScriptValue::Number(result.score as _)),
+                ]),
+            };
+            se.resolve_promise(request.id, value);
+        }
+        se.clear_draw_list();
         se.next_frame();
-        if let Some(bv) = se
-            .iso
-            .get_slot_mut::<IsoState>()
-            .expect("no state bound")
-            .new_border_state_buffer
-            .take()
-        {
-            let mut scope =
-                v8::HandleScope::with_context(&mut se.iso, &se.context);
-            let bv = v8::Local::new(&mut scope, bv);
-            let bs = bv.get_backing_store();
+        for r in se.take_search_requests() {
+            search_worker.request(r);
+        }
+        if let Some((position, to_move)) = se.board_position() {
+            analyzer.set_position(position, to_move);
+        }
+        se.set_evaluation(analyzer.evaluation());
+        renderer_2d.upload(se.draw_list_mut());
+        se.update_tweens();
+        se.upload_board_state(|bytes| {
             UNIFORM_BUFFER
-                .bind(buffers.board_state_buffer)
-                .subdata_ptr(bs.data(), bs.byte_length() as _, 0)
+                .bind(&buffers.board_state_buffer)
+                .subdata_ptr(bytes.as_ptr() as _, bytes.len() as _, 0)
                 .unbind();
+        });
+        if let Some(name) = se.take_requested_theme() {
+            if !themes.select(&name) {
+                log::warn!("Unknown theme: {}", name);
+            }
+        }
+        for (x, y, preset) in se.take_particle_emissions() {
+            particles.emit(x, y, &preset);
+        }
+        for (name, play_options) in se.take_sound_requests() {
+            audio.play(&name, &play_options);
+        }
+        audio.pump(elapsed);
+        particles.update((elapsed - last_elapsed).as_secs_f32() * 1000.0);
+        last_elapsed = elapsed;
+        update(
+            &buffers,
+            &shaders,
+            &particles,
+            themes.current(),
+            &renderer_2d,
+            [window_width as f32, window_height as f32],
+            &render_target,
+        );
+        gl_debug::check();
+        if screenshot_requested {
+            let path = format!("screenshot-{}.ppm", elapsed.as_millis());
+            match render_target.capture_screenshot(&path) {
+                Ok(()) => println!("Screenshot saved to {}", path),
+                Err(e) => println!("Failed to save screenshot: {}", e),
+            }
         }
-        update(&buffers, &shaders, elapsed.as_nanos() as f64 / 1_000_000.0);
         window.swap_buffers();
     }
 }
 
-fn update(buffers: &Buffers, shaders: &Shaders, time_ms: f64) {
+/// `solve` subcommand: prints the exact result of an endgame position
+fn solve_command(position: &Position, to_move: Color) {
+    let empties = position.empties().count_ones();
+    if empties > endgame::MAX_SOLVE_EMPTIES {
+        println!(
+            "Too many empty cells to solve: {} (at most {})",
+            empties,
+            endgame::MAX_SOLVE_EMPTIES
+        );
+        std::process::exit(1);
+    }
+
+    let solution = endgame::solve(position);
+    println!("{}", endgame::describe_result(solution.score, to_move));
+    match solution.best_move {
+        Some(sq) => println!(
+            "Best move: {}",
+            bitboard::square_name(bitboard::standard_square(sq))
+        ),
+        None => println!("No legal moves for {}", to_move.name()),
+    }
+    println!("{} nodes searched", solution.nodes);
+}
+
+/// `show-position` subcommand: prints the position in every format
+fn show_position_command(position: &Position, to_move: Color) {
+    for &f in &[
+        PositionFormat::Grid,
+        PositionFormat::Obf,
+        PositionFormat::Base64,
+    ] {
+        println!(
+            "{}\n",
+            position_codec::format_position(position, to_move, f)
+        );
+    }
+    println!("{} to move", to_move.name());
+}
+
+/// `build-book` subcommand
+fn build_book_command(transcripts: &str, output: &str) {
+    let result = std::fs::read_to_string(transcripts)
+        .map_err(|e| format!("Failed to load {}: {}", transcripts, e))
+        .and_then(|text| Book::build(&text))
+        .and_then(|book| book.save(output));
+    match result {
+        Ok(()) => println!("Book saved to {}", output),
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn update(
+    buffers: &Buffers,
+    shaders: &Shaders,
+    particles: &ParticleSystem,
+    theme: &Theme,
+    renderer_2d: &Renderer2D,
+    viewport_size: [f32; 2],
+    render_target: &RenderTarget,
+) {
     // atan(1.0/1.25)≒38deg
     const STONE_RENDER_WORLD_TRANSFORM: &'static [f32; 4 * 4] = &[
         1.0,
@@ -122,112 +427,101 @@ fn update(buffers: &Buffers, shaders: &Shaders, time_ms: f64) {
         1.0 + 1.25 * 10.0,
     ];
 
+    let board_size = buffers.board_size as i32;
+    let board_cell_count = buffers.board_size * buffers.board_size;
+
+    render_target.begin();
     unsafe {
-        gl::ClearColor(0.0, 0.4, 0.8, 1.0);
+        let [r, g, b] = theme.background;
+        gl::ClearColor(r, g, b, 1.0);
         gl::ClearDepth(1.0);
         gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 
-        gl::BindVertexArray(buffers.fillrect_va);
-        gl::UseProgram(shaders.board_base_render.0);
-        gl::Uniform1f(shaders.board_base_render_scale_uniform_location, 0.8);
+        buffers.fillrect_va.bind();
+        shaders
+            .board_base_render
+            .use_program()
+            .set_uniform("scale", 0.8f32)
+            .set_uniform("board_color", theme.board);
         gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
-        gl::UseProgram(shaders.board_grid_render.0);
-        gl::Uniform1f(shaders.board_grid_render_scale_uniform_location, 0.78);
+        shaders
+            .board_grid_render
+            .use_program()
+            .set_uniform("scale", 0.78f32)
+            .set_uniform("board_color", theme.board)
+            .set_uniform("grid_color", theme.grid)
+            .set_uniform("board_size", board_size);
         gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
-        gl::Enable(gl::DEPTH_TEST);
-        gl::UseProgram(shaders.stone_render.0);
-        gl::UniformMatrix4fv(
-            shaders.stone_render_wt_uniform_location,
-            1,
-            gl::FALSE,
-            STONE_RENDER_WORLD_TRANSFORM as _,
+
+        // blob shadows on the board surface, under everything drawn later
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        shaders
+            .shadow_render
+            .use_program()
+            .set_uniform("world_transform", *STONE_RENDER_WORLD_TRANSFORM)
+            .set_uniform("board_size", board_size);
+        buffers.board_state_buffer.bind_base(0);
+        gl::DrawArraysInstanced(
+            gl::TRIANGLE_STRIP,
+            0,
+            4,
+            board_cell_count as _,
         );
-        gl::Uniform1f(shaders.stone_render_time_uniform_location, time_ms as _);
-        gl::BindBufferBase(gl::UNIFORM_BUFFER, 0, buffers.board_state_buffer);
-        gl::BindVertexArray(buffers.stone_va);
+        gl::Disable(gl::BLEND);
+
+        gl::Enable(gl::DEPTH_TEST);
+        shaders
+            .stone_render
+            .use_program()
+            .set_uniform("world_transform", *STONE_RENDER_WORLD_TRANSFORM)
+            .set_uniform("black_color", theme.black_stone)
+            .set_uniform("white_color", theme.white_stone)
+            .set_uniform("board_size", board_size);
+        buffers.board_state_buffer.bind_base(0);
+        buffers.stone_va.bind();
         gl::DrawElementsInstanced(
             gl::TRIANGLES,
             buffers.stone_index_count as _,
             gl::UNSIGNED_SHORT,
             std::ptr::null(),
-            8 * 8,
+            board_cell_count as _,
         );
         UNIFORM_BUFFER.unbind();
-        gl::Disable(gl::DEPTH_TEST);
-        gl::BindVertexArray(0);
-    }
-}
+        VertexArray::unbind();
 
-struct BufferBindPoint(gl::types::GLenum);
-impl BufferBindPoint {
-    pub fn bind(&self, buf: gl::types::GLuint) -> &Self {
-        unsafe {
-            gl::BindBuffer(self.0, buf);
-        }
-        self
-    }
-    pub fn unbind(&self) -> &Self {
-        unsafe {
-            gl::BindBuffer(self.0, 0);
-        }
-        self
-    }
-    pub fn data<T>(&self, slice: &[T], usage: gl::types::GLenum) -> &Self {
-        unsafe {
-            gl::BufferData(
-                self.0,
-                (std::mem::size_of::<T>() * slice.len()) as _,
-                slice.as_ptr() as _,
-                usage,
-            );
-        }
-        self
-    }
-    pub fn alloc(
-        &self,
-        size: gl::types::GLsizeiptr,
-        usage: gl::types::GLenum,
-    ) -> &Self {
-        unsafe {
-            gl::BufferData(self.0, size, std::ptr::null(), usage);
-        }
-        self
-    }
-    pub fn subdata_ptr(
-        &self,
-        ptr: *mut c_void,
-        size: gl::types::GLsizeiptr,
-        offset: gl::types::GLintptr,
-    ) -> &Self {
-        unsafe {
-            gl::BufferSubData(self.0, offset, size, ptr);
-        }
-        self
+        // additive, occluded by stones but not writing depth
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE);
+        gl::DepthMask(gl::FALSE);
+        shaders
+            .particle_render
+            .use_program()
+            .set_uniform("world_transform", *STONE_RENDER_WORLD_TRANSFORM);
+        particles.draw();
+        gl::DepthMask(gl::TRUE);
+        gl::Disable(gl::BLEND);
+        gl::Disable(gl::DEPTH_TEST);
     }
-}
-static ARRAY_BUFFER: BufferBindPoint = BufferBindPoint(gl::ARRAY_BUFFER);
-static ELEMENT_ARRAY_BUFFER: BufferBindPoint =
-    BufferBindPoint(gl::ELEMENT_ARRAY_BUFFER);
-static UNIFORM_BUFFER: BufferBindPoint = BufferBindPoint(gl::UNIFORM_BUFFER);
-
-#[repr(C)]
-#[derive(Clone, Copy)]
-pub struct CellState {
-    pub state_flags: u32,
-    pub flip_start_time: f32,
+    renderer_2d.draw(&shaders.draw2d_render, viewport_size);
+    render_target.resolve();
 }
 
 struct Buffers {
-    fillrect_vb: gl::types::GLuint,
-    fillrect_va: gl::types::GLuint,
-    stone_vb: gl::types::GLuint,
-    stone_index_vb: gl::types::GLuint,
-    stone_va: gl::types::GLuint,
+    // vertex buffers are referenced only through vertex arrays
+    _fillrect_vb: Buffer<ArrayBufferTarget>,
+    fillrect_va: VertexArray,
+    _stone_vb: Buffer<ArrayBufferTarget>,
+    _stone_index_vb: Buffer<ElementArrayBufferTarget>,
+    stone_va: VertexArray,
     stone_index_count: usize,
-    board_state_buffer: gl::types::GLuint,
+    board_state_buffer: Buffer<UniformBufferTarget>,
+    /// Cells in a row. The first `board_size * board_size` cells of the
+    /// board state buffer are drawn.
+    board_size: usize,
 }
 impl Buffers {
-    pub fn new() -> Self {
+    pub fn new(board_size: usize) -> Self {
         const FILLRECT_VERTICES: &'static [[f32; 2]; 4] =
             &[[-1.0, -1.0], [1.0, -1.0], [-1.0, 1.0], [1.0, 1.0]];
         const STONE_SURFACE_VERTEX_COUNT: usize = 36;
@@ -265,33 +559,25 @@ impl Buffers {
             .chain(stone_surface_indices.iter().map(|&x| x * 2 + 1))
             .collect();
 
-        let mut vbs = [0, 0, 0, 0];
-        let mut vas = [0, 0];
-        unsafe {
-            gl::GenBuffers(vbs.len() as _, vbs.as_mut_ptr());
-            gl::GenVertexArrays(vas.len() as _, vas.as_mut_ptr());
-        }
-        let [fillrect_vb, stone_vb, stone_index_vb, board_state_buffer] = vbs;
-        let [fillrect_va, stone_va] = vas;
+        let fillrect_vb = Buffer::new();
+        let stone_vb = Buffer::new();
+        let stone_index_vb = Buffer::new();
+        let board_state_buffer = Buffer::new();
+        let fillrect_va = VertexArray::new();
+        let stone_va = VertexArray::new();
+        ARRAY_BUFFER
+            .bind(&fillrect_vb)
+            .data(FILLRECT_VERTICES, gl::STATIC_DRAW)
+            .bind(&stone_vb)
+            .data(&stone_vertices, gl::STATIC_DRAW)
+            .unbind();
+        ELEMENT_ARRAY_BUFFER
+            .bind(&stone_index_vb)
+            .data(&stone_indices, gl::STATIC_DRAW)
+            .unbind();
+        fillrect_va.bind();
+        ARRAY_BUFFER.bind(&fillrect_vb);
         unsafe {
-            ARRAY_BUFFER
-                .bind(fillrect_vb)
-                .data(FILLRECT_VERTICES, gl::STATIC_DRAW)
-                .bind(stone_vb)
-                .data(&stone_vertices, gl::STATIC_DRAW)
-                .unbind();
-            ELEMENT_ARRAY_BUFFER
-                .bind(stone_index_vb)
-                .data(&stone_indices, gl::STATIC_DRAW)
-                .unbind();
-            UNIFORM_BUFFER
-                .bind(board_state_buffer)
-                // Note: std140 layout uses 16 byte stride for arrays
-                .alloc(8 * 8 * 16, gl::DYNAMIC_DRAW)
-                .unbind();
-
-            gl::BindVertexArray(fillrect_va);
-            ARRAY_BUFFER.bind(fillrect_vb);
             gl::EnableVertexAttribArray(0);
             gl::VertexAttribPointer(
                 0,
@@ -301,8 +587,10 @@ impl Buffers {
                 0,
                 std::ptr::null(),
             );
-            gl::BindVertexArray(stone_va);
-            ARRAY_BUFFER.bind(stone_vb);
+        }
+        stone_va.bind();
+        ARRAY_BUFFER.bind(&stone_vb);
+        unsafe {
             gl::EnableVertexAttribArray(0);
             gl::VertexAttribPointer(
                 0,
@@ -312,244 +600,398 @@ impl Buffers {
                 0,
                 std::ptr::null(),
             );
-            ELEMENT_ARRAY_BUFFER.bind(stone_index_vb);
-            gl::BindVertexArray(0);
-            ELEMENT_ARRAY_BUFFER.unbind();
-            ARRAY_BUFFER.unbind();
         }
+        ELEMENT_ARRAY_BUFFER.bind(&stone_index_vb);
+        VertexArray::unbind();
+        ELEMENT_ARRAY_BUFFER.unbind();
+        ARRAY_BUFFER.unbind();
+
+        fillrect_vb.set_label("Fill Rect Vertices");
+        fillrect_va.set_label("Fill Rect");
+        stone_vb.set_label("Stone Vertices");
+        stone_index_vb.set_label("Stone Indices");
+        stone_va.set_label("Stone");
+        board_state_buffer.set_label("Board State");
 
-        Buffers {
-            fillrect_vb,
+        let buffers = Buffers {
+            _fillrect_vb: fillrect_vb,
             fillrect_va,
-            stone_vb,
-            stone_index_vb,
+            _stone_vb: stone_vb,
+            _stone_index_vb: stone_index_vb,
             stone_va,
             stone_index_count: stone_indices.len(),
             board_state_buffer,
-        }
-    }
-}
-impl Drop for Buffers {
-    fn drop(&mut self) {
-        unsafe {
-            gl::DeleteVertexArrays(
-                4,
-                [
-                    self.fillrect_vb,
-                    self.stone_vb,
-                    self.stone_index_vb,
-                    self.board_state_buffer,
-                ]
-                .as_ptr(),
-            );
-            gl::DeleteBuffers(2, [self.fillrect_va, self.stone_va].as_ptr());
-        }
-    }
-}
-
-struct Shader(gl::types::GLuint);
-impl Shader {
-    pub fn compile_file(ty: gl::types::GLenum, path: &str) -> Self {
-        let code =
-            std::fs::read_to_string(path).expect("Failed to load shader");
-        unsafe {
-            let sh = gl::CreateShader(ty);
-            gl::ShaderSource(
-                sh,
-                1,
-                &(code.as_ptr() as *const i8) as _,
-                &(code.len() as _),
-            );
-            gl::CompileShader(sh);
-            let mut compilation_succeeded_flag = 0;
-            gl::GetShaderiv(
-                sh,
-                gl::COMPILE_STATUS,
-                &mut compilation_succeeded_flag,
-            );
-            if compilation_succeeded_flag == gl::FALSE as _ {
-                let mut infolog_length = 0;
-                gl::GetShaderiv(sh, gl::INFO_LOG_LENGTH, &mut infolog_length);
-                let mut infolog = vec![0u8; infolog_length as usize];
-                gl::GetShaderInfoLog(
-                    sh,
-                    infolog_length,
-                    std::ptr::null_mut(),
-                    infolog.as_mut_ptr() as _,
-                );
-                panic!(
-                    "Shader Compilation was not successful: {}",
-                    std::ffi::CStr::from_bytes_with_nul_unchecked(&infolog)
-                        .to_str()
-                        .expect("invalid utf8 character in infolog")
-                );
-            }
-
-            Shader(sh)
-        }
-    }
-}
-impl Drop for Shader {
-    fn drop(&mut self) {
-        unsafe {
-            gl::DeleteShader(self.0);
-        }
+            board_size,
+        };
+        buffers.reset_board_state();
+        buffers
     }
-}
-struct Program(gl::types::GLuint);
-impl Program {
-    pub fn link_shaders(shaders: &[&Shader]) -> Self {
-        unsafe {
-            let p = gl::CreateProgram();
-            for sh in shaders {
-                gl::AttachShader(p, sh.0);
-            }
-            gl::LinkProgram(p);
-            let mut link_succeeded_flag = 0;
-            gl::GetProgramiv(p, gl::LINK_STATUS, &mut link_succeeded_flag);
-            if link_succeeded_flag == gl::FALSE as _ {
-                let mut infolog_length = 0;
-                gl::GetProgramiv(p, gl::INFO_LOG_LENGTH, &mut infolog_length);
-                let mut infolog = vec![0u8; infolog_length as usize];
-                gl::GetProgramInfoLog(
-                    p,
-                    infolog_length,
-                    std::ptr::null_mut(),
-                    infolog.as_mut_ptr() as _,
-                );
-                panic!(
-                    "Shader Linking was not successful: {}",
-                    std::ffi::CStr::from_bytes_with_nul_unchecked(&infolog)
-                        .to_str()
-                        .expect("invalid utf8 character in infolog")
-                );
-            }
 
-            Program(p)
-        }
-    }
-    pub fn uniform_location(
-        &self,
-        name: &std::ffi::CStr,
-    ) -> Option<gl::types::GLint> {
-        let r = unsafe { gl::GetUniformLocation(self.0, name.as_ptr()) };
-        if r < 0 {
-            None
-        } else {
-            Some(r)
-        }
-    }
-    pub fn uniform_block_location(
-        &self,
-        name: &std::ffi::CStr,
-    ) -> gl::types::GLuint {
-        unsafe { gl::GetUniformBlockIndex(self.0, name.as_ptr()) }
-    }
-}
-impl Drop for Program {
-    fn drop(&mut self) {
-        unsafe {
-            gl::DeleteProgram(self.0);
-        }
+    /// Fills the board state buffer with empty cells, keeping the buffer
+    pub fn reset_board_state(&self) {
+        UNIFORM_BUFFER
+            .bind(&self.board_state_buffer)
+            .data(
+                &std140::array_bytes(&[CellState::default(); BOARD_CELL_COUNT]),
+                gl::DYNAMIC_DRAW,
+            )
+            .unbind();
     }
 }
 
 struct Shaders {
     board_base_render: Program,
-    board_base_render_scale_uniform_location: gl::types::GLint,
     board_grid_render: Program,
-    board_grid_render_scale_uniform_location: gl::types::GLint,
+    shadow_render: Program,
     stone_render: Program,
-    stone_render_wt_uniform_location: gl::types::GLint,
-    stone_render_time_uniform_location: gl::types::GLint,
+    particle_render: Program,
+    draw2d_render: Program,
 }
 impl Shaders {
     pub fn new() -> Self {
+        match Self::load() {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Recompiles all shaders from assets.
+    /// Previous programs are kept active if any of them fails to build.
+    pub fn reload(&mut self) {
+        match Self::load() {
+            Ok(s) => {
+                *self = s;
+                log::info!("Shaders reloaded");
+            }
+            Err(e) => log::warn!(
+                "Shader reload failed, keeping previous programs\n{}",
+                e
+            ),
+        }
+    }
+
+    fn load() -> Result<Self, String> {
         let scaled_vsh =
-            Shader::compile_file(gl::VERTEX_SHADER, "./assets/scaled.vsh");
+            Shader::compile_file(gl::VERTEX_SHADER, "./assets/scaled.vsh")?;
         let board_base_fsh = Shader::compile_file(
             gl::FRAGMENT_SHADER,
             "./assets/board_base.fsh",
-        );
+        )?;
         let board_grid_fsh = Shader::compile_file(
             gl::FRAGMENT_SHADER,
             "./assets/board_grid.fsh",
-        );
+        )?;
+        let shadow_vsh =
+            Shader::compile_file(gl::VERTEX_SHADER, "./assets/shadow.vsh")?;
+        let shadow_fsh =
+            Shader::compile_file(gl::FRAGMENT_SHADER, "./assets/shadow.fsh")?;
         let stone_vsh =
-            Shader::compile_file(gl::VERTEX_SHADER, "./assets/stone.vsh");
+            Shader::compile_file(gl::VERTEX_SHADER, "./assets/stone.vsh")?;
         let stone_fsh =
-            Shader::compile_file(gl::FRAGMENT_SHADER, "./assets/stone.fsh");
+            Shader::compile_file(gl::FRAGMENT_SHADER, "./assets/stone.fsh")?;
+        let particle_vsh =
+            Shader::compile_file(gl::VERTEX_SHADER, "./assets/particle.vsh")?;
+        let particle_fsh =
+            Shader::compile_file(gl::FRAGMENT_SHADER, "./assets/particle.fsh")?;
+        let draw2d_vsh =
+            Shader::compile_file(gl::VERTEX_SHADER, "./assets/draw2d.vsh")?;
+        let draw2d_fsh =
+            Shader::compile_file(gl::FRAGMENT_SHADER, "./assets/draw2d.fsh")?;
 
         let board_base_render =
-            Program::link_shaders(&[&scaled_vsh, &board_base_fsh]);
-        let board_base_render_scale_uniform_location = board_base_render
-            .uniform_location(unsafe {
-                std::ffi::CStr::from_bytes_with_nul_unchecked(b"scale\0")
-            })
-            .expect("no scale uniform defined");
+            Program::link_shaders(&[&scaled_vsh, &board_base_fsh])?;
+        board_base_render.require_uniform::<f32>("scale")?;
+        board_base_render.require_uniform::<[f32; 3]>("board_color")?;
         let board_grid_render =
-            Program::link_shaders(&[&scaled_vsh, &board_grid_fsh]);
-        let board_grid_render_scale_uniform_location = board_grid_render
-            .uniform_location(unsafe {
-                std::ffi::CStr::from_bytes_with_nul_unchecked(b"scale\0")
-            })
-            .expect("no scale uniform defined");
-        let stone_render = Program::link_shaders(&[&stone_vsh, &stone_fsh]);
-        let stone_render_wt_uniform_location = stone_render
-            .uniform_location(unsafe {
-                std::ffi::CStr::from_bytes_with_nul_unchecked(
-                    b"world_transform\0",
-                )
-            })
-            .expect("no world transform uniform defined");
-        let stone_render_time_uniform_location = stone_render
-            .uniform_location(unsafe {
-                std::ffi::CStr::from_bytes_with_nul_unchecked(b"time_ms\0")
-            })
-            .expect("no time uniform defined");
-        let board_state_uniform_block_location = stone_render
-            .uniform_block_location(unsafe {
-                std::ffi::CStr::from_bytes_with_nul_unchecked(b"BoardState\0")
-            });
-        unsafe {
-            gl::UniformBlockBinding(
-                stone_render.0,
-                board_state_uniform_block_location,
-                0,
-            );
-        }
+            Program::link_shaders(&[&scaled_vsh, &board_grid_fsh])?;
+        board_grid_render.require_uniform::<f32>("scale")?;
+        board_grid_render.require_uniform::<[f32; 3]>("board_color")?;
+        board_grid_render.require_uniform::<[f32; 3]>("grid_color")?;
+        board_grid_render.require_uniform::<i32>("board_size")?;
+        // drawn with the fillrect vertex array
+        let shadow_render = Program::link_shaders_with_attributes(
+            &[&shadow_vsh, &shadow_fsh],
+            &[(0, "corner")],
+        )?;
+        shadow_render.require_uniform::<[f32; 4 * 4]>("world_transform")?;
+        shadow_render.require_uniform::<i32>("board_size")?;
+        shadow_render.bind_uniform_block_array::<CellState>(
+            "BoardState",
+            "cells",
+            BOARD_CELL_COUNT,
+            0,
+        )?;
+        let stone_render = Program::link_shaders(&[&stone_vsh, &stone_fsh])?;
+        stone_render.require_uniform::<[f32; 4 * 4]>("world_transform")?;
+        stone_render.require_uniform::<[f32; 3]>("black_color")?;
+        stone_render.require_uniform::<[f32; 3]>("white_color")?;
+        stone_render.require_uniform::<i32>("board_size")?;
+        stone_render.bind_uniform_block_array::<CellState>(
+            "BoardState",
+            "cells",
+            BOARD_CELL_COUNT,
+            0,
+        )?;
+        // locations match the vertex array of ParticleSystem
+        let particle_render = Program::link_shaders_with_attributes(
+            &[&particle_vsh, &particle_fsh],
+            &[(0, "corner"), (1, "position_size"), (2, "color")],
+        )?;
+        particle_render.require_uniform::<[f32; 4 * 4]>("world_transform")?;
+        // locations match the vertex array of Renderer2D
+        let draw2d_render = Program::link_shaders_with_attributes(
+            &[&draw2d_vsh, &draw2d_fsh],
+            &[
+                (0, "position"),
+                (1, "local"),
+                (2, "half_size"),
+                (3, "params"),
+                (4, "color"),
+            ],
+        )?;
+        draw2d_render.require_uniform::<[f32; 2]>("viewport_size")?;
+        draw2d_render.require_uniform::<TextureUnit>("image")?;
+
+        board_base_render.set_label("Board Base Render");
+        board_grid_render.set_label("Board Grid Render");
+        shadow_render.set_label("Shadow Render");
+        stone_render.set_label("Stone Render");
+        particle_render.set_label("Particle Render");
+        draw2d_render.set_label("2D Draw List Render");
 
-        Shaders {
+        Ok(Shaders {
             board_base_render,
-            board_base_render_scale_uniform_location,
             board_grid_render,
-            board_grid_render_scale_uniform_location,
+            shadow_render,
             stone_render,
-            stone_render_wt_uniform_location,
-            stone_render_time_uniform_location,
-        }
+            particle_render,
+            draw2d_render,
+        })
     }
 }
 
 pub struct IsoState {
+    /// Cells in a row, fixed at startup
+    pub board_size: usize,
+    /// Side played by the engine, None for two human players
+    pub ai_color: Option<Color>,
+    pub book: Option<Arc<Book>>,
     pub next_frame_callbacks: Vec<v8::Global<v8::Function>>,
     pub cursor_pos: (f64, f64),
     pub button_pressing: bool,
-    pub new_border_state_buffer: Option<v8::Global<v8::ArrayBuffer>>,
+    /// Buffer last set by the script, also written by tweens
+    pub board_state_buffer: Option<v8::Global<v8::ArrayBuffer>>,
+    /// The buffer has been set or modified since the last upload
+    pub board_state_dirty: bool,
     pub current_time_ms: f64,
+    /// (cell x, cell y, preset name)
+    pub particle_emissions: Vec<(f64, f64, String)>,
+    pub requested_theme: Option<String>,
+    pub draw_list: DrawList,
+    pub sound_requests: Vec<(String, PlayOptions)>,
+    pub tweens: TweenScheduler,
+    /// Promises resolved from Rust, by id
+    pub pending_promises: HashMap<u32, v8::Global<v8::PromiseResolver>>,
+    pub next_promise_id: u32,
+    /// Engine searches requested by scripts, keyed by promise ids
+    pub search_requests: Vec<SearchRequest>,
+    /// Latest background analysis of the board state
+    pub evaluation: Option<Evaluation>,
+    /// Moves recorded by the script
+    pub history: MoveHistory,
+    /// Undo and redo requested by keys, oldest first
+    pub history_commands: Vec<HistoryCommand>,
+    /// Game to be restored by the script
+    pub loaded_game: Option<GameRecord>,
+    /// Position new games start from, the initial one or `--position`
+    pub initial_position: (Position, Color),
+    /// Position the current game started from, seen from the side to move.
+    /// A loaded game may have started elsewhere.
+    pub start_position: (Position, Color),
+    pub game: Game,
+    /// Events not yet delivered to the script, oldest first
+    pub game_events: Vec<GameEvent>,
+    pub game_event_callbacks: Vec<v8::Global<v8::Function>>,
+    pub time_control: Option<TimeControl>,
+    /// Stops the clock while tweens are running
+    pub clock_pauses_for_animations: bool,
+    pub clock: Option<GameClock>,
+    /// Set by the N key or `newGame()`, handled between frames
+    pub new_game_requested: bool,
 }
 impl IsoState {
-    pub fn new() -> Self {
-        Self {
+    pub fn new(
+        board_size: usize,
+        ai_color: Option<Color>,
+        book: Option<Arc<Book>>,
+    ) -> Self {
+        let mut state = Self {
+            board_size,
+            ai_color,
+            book,
             next_frame_callbacks: Vec::new(),
             cursor_pos: (0.0, 0.0),
             button_pressing: false,
-            new_border_state_buffer: None,
+            board_state_buffer: None,
+            board_state_dirty: false,
             current_time_ms: 0.0,
+            particle_emissions: Vec::new(),
+            requested_theme: None,
+            draw_list: DrawList::new(),
+            sound_requests: Vec::new(),
+            tweens: TweenScheduler::new(),
+            pending_promises: HashMap::new(),
+            next_promise_id: 0,
+            search_requests: Vec::new(),
+            evaluation: None,
+            history: MoveHistory::new(),
+            history_commands: Vec::new(),
+            loaded_game: None,
+            initial_position: (Position::initial(), Color::Black),
+            start_position: (Position::initial(), Color::Black),
+            game: Game::new(board_size),
+            game_events: Vec::new(),
+            game_event_callbacks: Vec::new(),
+            time_control: None,
+            clock_pauses_for_animations: false,
+            clock: None,
+            new_game_requested: false,
+        };
+        state.restart_game();
+        state
+    }
+
+    /// Game at the start position, before settling the first turn
+    fn initial_game(&self) -> Game {
+        if self.board_size == bitboard::SIZE {
+            let (position, to_move) = self.start_position;
+            Game::from_position(&position, to_move)
+        } else {
+            Game::new(self.board_size)
+        }
+    }
+
+    /// Starts over from the initial position with an empty history
+    pub fn restart_game(&mut self) {
+        self.start_position = self.initial_position;
+        self.history = MoveHistory::new();
+        self.game = self.initial_game();
+        self.game_events.clear();
+        self.clock = self.time_control.map(GameClock::new);
+        let events = self.game.begin();
+        self.push_game_events(events);
+    }
+
+    /// Replays the played moves of the history, for undo and loaded games.
+    /// Only the resulting state is reported.
+    pub fn replay_game(&mut self) {
+        let mut game = self.initial_game();
+        game.begin();
+        for e in self.history.played() {
+            if let Some((x, y)) = e.cell {
+                // passes follow from the rules
+                game.play(x, y).expect("illegal move in the history");
+            }
+        }
+        self.game = game;
+        let event = self.game.state_event();
+        self.queue_game_events(vec![event]);
+    }
+
+    /// Records moves and passes in the history and queues the events
+    fn push_game_events(&mut self, events: Vec<GameEvent>) {
+        for e in &events {
+            let (color, cell, flipped) = match e {
+                GameEvent::Placed {
+                    color,
+                    cell,
+                    flipped,
+                } => (*color, Some(*cell), flipped.clone()),
+                GameEvent::Passed { color } => (*color, None, Vec::new()),
+                _ => continue,
+            };
+            self.history.record(HistoryEntry {
+                color,
+                cell,
+                flipped,
+                time_ms: self.current_time_ms,
+            });
+        }
+        self.queue_game_events(events);
+    }
+
+    /// Queues events for the script, running the clock of the side to move
+    fn queue_game_events(&mut self, events: Vec<GameEvent>) {
+        if let Some(clock) = &mut self.clock {
+            for e in &events {
+                match e {
+                    GameEvent::Placed { color, .. } => {
+                        clock.complete_move(*color)
+                    }
+                    GameEvent::Turn { color, .. } => {
+                        clock.start(*color, self.current_time_ms)
+                    }
+                    GameEvent::Ended(_) => clock.stop(),
+                    GameEvent::Passed { .. } => (),
+                }
+            }
+        }
+        self.game_events.extend(events);
+    }
+
+    /// Charges the clock for the time since the last frame, ending the game
+    /// when a flag falls
+    pub fn tick_clock(&mut self) {
+        let paused =
+            self.clock_pauses_for_animations && self.tweens.is_active();
+        let flagged = match &mut self.clock {
+            Some(clock) => clock.tick(self.current_time_ms, paused),
+            None => return,
+        };
+        if let Some(color) = flagged {
+            let events = self
+                .game
+                .flag_fall(color)
+                .expect("the clock ran after the game");
+            self.queue_game_events(events);
+        }
+    }
+
+    /// Drops everything the running script waits on or subscribed to, so
+    /// that it never resumes, and starts a new game. A loaded game not yet
+    /// taken is kept for the next script.
+    fn reset_session(&mut self) {
+        self.next_frame_callbacks.clear();
+        self.game_event_callbacks.clear();
+        // results of searches in flight are ignored by their ids
+        self.pending_promises.clear();
+        self.search_requests.clear();
+        self.tweens = TweenScheduler::new();
+        self.board_state_buffer = None;
+        self.board_state_dirty = false;
+        self.history_commands.clear();
+        self.evaluation = None;
+        self.new_game_requested = false;
+        self.restart_game();
+    }
+
+    /// Reads a game record for the script to restore
+    pub fn load_game(&mut self, path: &str) -> Result<(), String> {
+        if self.board_size != bitboard::SIZE {
+            return Err("game records are supported only on 8x8 boards".into());
         }
+        self.loaded_game = Some(GameRecord::load(path)?);
+        Ok(())
     }
 }
 
+fn throw_type_error(scope: &mut v8::HandleScope, message: &str) {
+    let msg = v8::String::new(scope, message)
+        .expect("Failed to create error message");
+    let err = v8::Exception::type_error(scope, msg);
+    scope.throw_exception(err);
+}
+
 fn request_next_frame(
     scope: &mut v8::HandleScope,
     args: v8::FunctionCallbackArguments,
@@ -557,13 +999,7 @@ fn request_next_frame(
 ) {
     let f = match v8::Local::<v8::Function>::try_from(args.get(0)) {
         Ok(f) => v8::Global::new(scope, f),
-        Err(e) => {
-            let msg = v8::String::new(scope, &e.to_string())
-                .expect("Failed to create error message");
-            let err = v8::Exception::type_error(scope, msg);
-            scope.throw_exception(err);
-            return;
-        }
+        Err(e) => return throw_type_error(scope, &e.to_string()),
     };
 
     scope
@@ -605,19 +1041,24 @@ fn set_board_state_buffer(
 ) {
     let v = match v8::Local::<v8::ArrayBuffer>::try_from(args.get(0)) {
         Ok(v) => v8::Global::new(scope, v),
-        Err(e) => {
-            let msg = v8::String::new(scope, &e.to_string())
-                .expect("Failed to create error message");
-            let err = v8::Exception::type_error(scope, msg);
-            scope.throw_exception(err);
-            return;
-        }
+        Err(e) => return throw_type_error(scope, &e.to_string()),
     };
 
-    scope
-        .get_slot_mut::<IsoState>()
+    let state = scope.get_slot_mut::<IsoState>().expect("no state bound");
+    state.board_state_buffer = Some(v);
+    state.board_state_dirty = true;
+}
+fn board_size(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let size = scope
+        .get_slot::<IsoState>()
         .expect("no state bound")
-        .new_border_state_buffer = Some(v);
+        .board_size;
+    let v = v8::Integer::new(scope, size as i32);
+    rv.set(v.into());
 }
 fn current_time_ms(
     scope: &mut v8::HandleScope,
@@ -631,30 +1072,1128 @@ fn current_time_ms(
     let v = v8::Number::new(scope, t);
     rv.set(v.into());
 }
+fn emit_particles(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let (x, y) = match (
+        v8::Local::<v8::Number>::try_from(args.get(0)),
+        v8::Local::<v8::Number>::try_from(args.get(1)),
+    ) {
+        (Ok(x), Ok(y)) => (x.value(), y.value()),
+        _ => return throw_type_error(scope, "cell position must be numbers"),
+    };
+    let preset = match v8::Local::<v8::String>::try_from(args.get(2)) {
+        Ok(s) => s.to_rust_string_lossy(scope),
+        Err(e) => return throw_type_error(scope, &e.to_string()),
+    };
 
-pub struct ScriptEngine {
-    // Note: Inspectors must be destroyed before isolate destruction
-    _inspector: v8::UniqueRef<v8::inspector::V8Inspector>,
-    _inspector_client: Box<ScriptInspectorClient>,
-    iso: v8::OwnedIsolate,
-    context: v8::Global<v8::Context>,
+    scope
+        .get_slot_mut::<IsoState>()
+        .expect("no state bound")
+        .particle_emissions
+        .push((x, y, preset));
 }
-impl ScriptEngine {
-    pub fn new() -> Self {
-        let platform = v8::new_default_platform().unwrap();
-        v8::V8::initialize_platform(platform);
-        v8::V8::initialize();
+fn set_theme(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let name = match v8::Local::<v8::String>::try_from(args.get(0)) {
+        Ok(s) => s.to_rust_string_lossy(scope),
+        Err(e) => return throw_type_error(scope, &e.to_string()),
+    };
 
-        let mut iso = v8::Isolate::new(v8::CreateParams::default());
-        iso.set_slot(IsoState::new());
-        let mut inspector_client = Box::new(ScriptInspectorClient::new());
-        let mut inspector = v8::inspector::V8Inspector::create(
-            &mut iso,
-            &mut *inspector_client,
-        );
-        let context = {
-            let mut scope = v8::HandleScope::new(&mut iso);
-            let context = v8::Context::new(&mut scope);
+    scope
+        .get_slot_mut::<IsoState>()
+        .expect("no state bound")
+        .requested_theme = Some(name);
+}
+
+/// Reads `count` numbers from the arguments starting at `first`
+fn number_args(
+    args: &v8::FunctionCallbackArguments,
+    first: i32,
+    count: i32,
+) -> Option<Vec<f32>> {
+    (first..first + count)
+        .map(|n| {
+            v8::Local::<v8::Number>::try_from(args.get(n))
+                .ok()
+                .map(|v| v.value() as f32)
+        })
+        .collect()
+}
+/// Reads `[r, g, b, a?]` (each 0.0..=1.0, alpha defaults to 1.0)
+fn color_arg(
+    scope: &mut v8::HandleScope,
+    value: v8::Local<v8::Value>,
+) -> Option<[f32; 4]> {
+    let a = v8::Local::<v8::Array>::try_from(value).ok()?;
+    if a.length() < 3 {
+        return None;
+    }
+    let mut color = [1.0; 4];
+    for (n, c) in color.iter_mut().enumerate().take(a.length() as usize) {
+        let v = a.get_index(scope, n as u32)?;
+        *c = v8::Local::<v8::Number>::try_from(v).ok()?.value() as f32;
+    }
+    Some(color)
+}
+fn draw_list_mut<'s>(scope: &'s mut v8::HandleScope) -> &'s mut DrawList {
+    &mut scope
+        .get_slot_mut::<IsoState>()
+        .expect("no state bound")
+        .draw_list
+}
+fn draw_rect(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    match (number_args(&args, 0, 4), color_arg(scope, args.get(4))) {
+        (Some(n), Some(color)) => {
+            draw_list_mut(scope).rect(n[0], n[1], n[2], n[3], color)
+        }
+        _ => throw_type_error(scope, "drawRect(x, y, w, h, color)"),
+    }
+}
+fn draw_rounded_rect(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    match (number_args(&args, 0, 5), color_arg(scope, args.get(5))) {
+        (Some(n), Some(color)) => draw_list_mut(scope)
+            .rounded_rect(n[0], n[1], n[2], n[3], n[4], color),
+        _ => throw_type_error(
+            scope,
+            "drawRoundedRect(x, y, w, h, radius, color)",
+        ),
+    }
+}
+fn draw_circle(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    match (number_args(&args, 0, 3), color_arg(scope, args.get(3))) {
+        (Some(n), Some(color)) => {
+            draw_list_mut(scope).circle(n[0], n[1], n[2], color)
+        }
+        _ => throw_type_error(scope, "drawCircle(cx, cy, radius, color)"),
+    }
+}
+fn draw_line(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    match (number_args(&args, 0, 5), color_arg(scope, args.get(5))) {
+        (Some(n), Some(color)) => {
+            draw_list_mut(scope).line(n[0], n[1], n[2], n[3], n[4], color)
+        }
+        _ => throw_type_error(scope, "drawLine(x0, y0, x1, y1, width, color)"),
+    }
+}
+fn draw_image(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    // color is optional for images
+    let color = if args.get(5).is_undefined() {
+        Some([1.0; 4])
+    } else {
+        color_arg(scope, args.get(5))
+    };
+    match (number_args(&args, 0, 5), color) {
+        (Some(n), Some(color)) => draw_list_mut(scope).image(
+            n[0] as u32,
+            n[1],
+            n[2],
+            n[3],
+            n[4],
+            color,
+        ),
+        _ => throw_type_error(scope, "drawImage(texture, x, y, w, h, color?)"),
+    }
+}
+fn load_texture(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let path = match v8::Local::<v8::String>::try_from(args.get(0)) {
+        Ok(s) => s.to_rust_string_lossy(scope),
+        Err(e) => return throw_type_error(scope, &e.to_string()),
+    };
+
+    match draw_list_mut(scope).load_texture(&path) {
+        Ok(id) => {
+            let v = v8::Integer::new_from_unsigned(scope, id);
+            rv.set(v.into());
+        }
+        Err(e) => {
+            let msg = v8::String::new(scope, &e)
+                .expect("Failed to create error message");
+            let err = v8::Exception::error(scope, msg);
+            scope.throw_exception(err);
+        }
+    }
+}
+/// Reads a number property, None if it is undefined
+fn number_property(
+    scope: &mut v8::HandleScope,
+    obj: v8::Local<v8::Object>,
+    key: &str,
+) -> Option<f64> {
+    let key =
+        v8::String::new(scope, key).expect("Failed to create property name");
+    obj.get(scope, key.into())
+        .filter(|v| !v.is_undefined())
+        .and_then(|v| v.number_value(scope))
+}
+/// Reads a string property, None if it is undefined
+fn string_property(
+    scope: &mut v8::HandleScope,
+    obj: v8::Local<v8::Object>,
+    key: &str,
+) -> Option<String> {
+    let key =
+        v8::String::new(scope, key).expect("Failed to create property name");
+    obj.get(scope, key.into())
+        .filter(|v| !v.is_undefined())
+        .map(|v| v.to_rust_string_lossy(scope))
+}
+fn play_sound(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let name = match v8::Local::<v8::String>::try_from(args.get(0)) {
+        Ok(s) => s.to_rust_string_lossy(scope),
+        Err(e) => return throw_type_error(scope, &e.to_string()),
+    };
+    let mut options = PlayOptions::default();
+    if !args.get(1).is_undefined() {
+        let obj = match v8::Local::<v8::Object>::try_from(args.get(1)) {
+            Ok(o) => o,
+            Err(e) => return throw_type_error(scope, &e.to_string()),
+        };
+        // { volume?: number, pan?: number, column?: number }
+        if let Some(v) = number_property(scope, obj, "volume") {
+            options.volume = v as f32;
+        }
+        if let Some(v) = number_property(scope, obj, "pan") {
+            options.pan = v as f32;
+        }
+        if let Some(v) = number_property(scope, obj, "column") {
+            let board_size = scope
+                .get_slot::<IsoState>()
+                .expect("no state bound")
+                .board_size;
+            options.pan = PlayOptions::column_pan(v, board_size);
+        }
+    }
+
+    scope
+        .get_slot_mut::<IsoState>()
+        .expect("no state bound")
+        .sound_requests
+        .push((name, options));
+}
+/// Creates a promise to be resolved from Rust by
+/// `ScriptEngine::resolve_promise`
+fn new_pending_promise<'s>(
+    scope: &mut v8::HandleScope<'s>,
+) -> (u32, v8::Local<'s, v8::Promise>) {
+    let resolver =
+        v8::PromiseResolver::new(scope).expect("Failed to create promise");
+    let promise = resolver.get_promise(scope);
+    let resolver = v8::Global::new(scope, resolver);
+    let state = scope.get_slot_mut::<IsoState>().expect("no state bound");
+    let id = state.next_promise_id;
+    state.next_promise_id = id.wrapping_add(1);
+    state.pending_promises.insert(id, resolver);
+
+    (id, promise)
+}
+/// Reads `[[x, y], ...]` as cell indices
+fn cells_arg(
+    scope: &mut v8::HandleScope,
+    value: v8::Local<v8::Value>,
+) -> Option<Vec<usize>> {
+    let board_size = scope
+        .get_slot::<IsoState>()
+        .expect("no state bound")
+        .board_size;
+    let cells = v8::Local::<v8::Array>::try_from(value).ok()?;
+    (0..cells.length())
+        .map(|n| {
+            let c = cells.get_index(scope, n)?;
+            cell_arg(scope, board_size, c)
+        })
+        .collect()
+}
+/// Cell index of `[x, y]`, None if out of the board
+fn cell_arg(
+    scope: &mut v8::HandleScope,
+    board_size: usize,
+    value: v8::Local<v8::Value>,
+) -> Option<usize> {
+    let c = v8::Local::<v8::Array>::try_from(value).ok()?;
+    let x = c.get_index(scope, 0)?.integer_value(scope)?;
+    let y = c.get_index(scope, 1)?.integer_value(scope)?;
+    cell_index(board_size, x, y)
+}
+/// `{ duration, delay?, stagger?, easing? }`
+fn animation_options_arg(
+    scope: &mut v8::HandleScope,
+    value: v8::Local<v8::Value>,
+    default_easing: Easing,
+) -> Result<AnimationOptions, String> {
+    let obj = v8::Local::<v8::Object>::try_from(value)
+        .map_err(|_| "animation options must be an object".to_owned())?;
+    let duration_ms = number_property(scope, obj, "duration")
+        .ok_or("duration is required")?;
+    let easing = match string_property(scope, obj, "easing") {
+        None => default_easing,
+        Some(e) => {
+            Easing::parse(&e).ok_or_else(|| format!("unknown easing: {}", e))?
+        }
+    };
+
+    Ok(AnimationOptions {
+        duration_ms,
+        delay_ms: number_property(scope, obj, "delay").unwrap_or(0.0),
+        stagger_ms: number_property(scope, obj, "stagger").unwrap_or(0.0),
+        easing,
+    })
+}
+/// Schedules tweens of a float field of cells and returns the promise
+/// resolved when all of them have finished
+fn schedule_cell_tweens<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    cells: &[usize],
+    field_offset: usize,
+    range: (f32, f32),
+    options: &AnimationOptions,
+) -> v8::Local<'s, v8::Promise> {
+    let (promise_id, promise) = new_pending_promise(scope);
+    let state = scope.get_slot_mut::<IsoState>().expect("no state bound");
+    let now_ms = state.current_time_ms;
+    state.tweens.add_cells(
+        now_ms,
+        cells,
+        field_offset,
+        range,
+        options,
+        promise_id,
+    );
+
+    promise
+}
+fn animate_cells(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let cells = match cells_arg(scope, args.get(0)) {
+        Some(c) => c,
+        None => return throw_type_error(scope, "cells must be [x, y][]"),
+    };
+    let field = args.get(1).to_rust_string_lossy(scope);
+    let field_offset = match cell_float_field(&field) {
+        Some(o) => o,
+        None => {
+            return throw_type_error(
+                scope,
+                &format!("{} is not a float field of CellState", field),
+            )
+        }
+    };
+    let options =
+        match animation_options_arg(scope, args.get(2), Easing::Linear) {
+            Ok(o) => o,
+            Err(e) => return throw_type_error(scope, &e),
+        };
+    let obj = v8::Local::<v8::Object>::try_from(args.get(2))
+        .expect("checked by animation_options_arg");
+    let range = match (
+        number_property(scope, obj, "from"),
+        number_property(scope, obj, "to"),
+    ) {
+        (Some(from), Some(to)) => (from as f32, to as f32),
+        _ => return throw_type_error(scope, "from and to are required"),
+    };
+
+    let promise =
+        schedule_cell_tweens(scope, &cells, field_offset, range, &options);
+    rv.set(promise.into());
+}
+fn animate_flip(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let cells = match cells_arg(scope, args.get(0)) {
+        Some(c) => c,
+        None => return throw_type_error(scope, "cells must be [x, y][]"),
+    };
+    let options =
+        match animation_options_arg(scope, args.get(1), Easing::EaseOutQuart) {
+            Ok(o) => o,
+            Err(e) => return throw_type_error(scope, &e),
+        };
+    let field_offset =
+        cell_float_field("flipProgress").expect("no flipProgress in CellState");
+
+    let promise =
+        schedule_cell_tweens(scope, &cells, field_offset, (0.0, 1.0), &options);
+    rv.set(promise.into());
+}
+fn wait(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let duration_ms = match v8::Local::<v8::Number>::try_from(args.get(0)) {
+        Ok(v) => v.value(),
+        Err(e) => return throw_type_error(scope, &e.to_string()),
+    };
+
+    let (promise_id, promise) = new_pending_promise(scope);
+    let state = scope.get_slot_mut::<IsoState>().expect("no state bound");
+    let start_ms = state.current_time_ms;
+    state.tweens.add(Tween {
+        target: None,
+        from: 0.0,
+        to: 0.0,
+        start_ms,
+        duration_ms,
+        easing: Easing::Linear,
+        promise: promise_id,
+    });
+    rv.set(promise.into());
+}
+
+/// `state_flags` of the cells in the buffer set by the script
+fn board_state_flags(scope: &mut v8::HandleScope) -> Option<Vec<u32>> {
+    let buffer = scope
+        .get_slot_mut::<IsoState>()
+        .expect("no state bound")
+        .board_state_buffer
+        .take()?;
+    let bs = v8::Local::new(scope, &buffer).get_backing_store();
+    scope
+        .get_slot_mut::<IsoState>()
+        .expect("no state bound")
+        .board_state_buffer = Some(buffer);
+
+    Some(with_backing_store_bytes(Some(&bs), read_state_flags))
+}
+/// Current board state seen from `to_move`
+fn engine_position(
+    scope: &mut v8::HandleScope,
+    to_move: Color,
+) -> Result<Position, &'static str> {
+    let board_size = scope
+        .get_slot::<IsoState>()
+        .expect("no state bound")
+        .board_size;
+    if board_size != bitboard::SIZE {
+        return Err("the engine supports only 8x8 boards");
+    }
+    let flags =
+        board_state_flags(scope).ok_or("board state buffer is not set")?;
+    Ok(Position::from_cell_flags(&flags, to_move))
+}
+/// `bookMoves(color?)`: candidate moves from the opening book as
+/// `[{ move: [x, y], weight, score }]`. The side to move defaults to the one
+/// of the game.
+fn book_moves(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let color = if args.get(0).is_null_or_undefined() {
+        None
+    } else {
+        match v8::Local::<v8::String>::try_from(args.get(0)) {
+            Ok(s) => match Color::parse(&s.to_rust_string_lossy(scope)) {
+                Some(c) => Some(c),
+                None => {
+                    return throw_type_error(
+                        scope,
+                        "color must be black or white",
+                    )
+                }
+            },
+            Err(e) => return throw_type_error(scope, &e.to_string()),
+        }
+    };
+    let position = scope
+        .get_slot::<IsoState>()
+        .expect("no state bound")
+        .game
+        .engine_position();
+    let position = match (position, color) {
+        (Some((p, to_move)), Some(c)) if c != to_move => p.pass(),
+        (Some((p, _)), _) => p,
+        (None, _) => {
+            return throw_type_error(
+                scope,
+                "the engine supports only 8x8 boards",
+            )
+        }
+    };
+
+    let book = scope
+        .get_slot::<IsoState>()
+        .expect("no state bound")
+        .book
+        .clone();
+    let moves = book.as_ref().map_or_else(Vec::new, |b| {
+        b.lookup(&position)
+            .into_iter()
+            .map(|(sq, m)| {
+                ScriptValue::Object(vec![
+                    ("move", ScriptValue::cell(Some(sq))),
+                    ("weight", ScriptValue::Number(m.weight as _)),
+                    (
+                        "score",
+                        m.score.map_or(ScriptValue::Null, |s| {
+                            ScriptValue::Number(s as _)
+                        }),
+                    ),
+                ])
+            })
+            .collect()
+    });
+    let v = ScriptValue::Array(moves).to_v8(scope);
+    rv.set(v);
+}
+/// `currentEvaluation()`: latest analysis of the board state as
+/// `{ score, exact, depth, advantage }` from black, or null if not analyzed
+/// yet. `advantage` is from -1 (white wins) to 1 (black wins).
+fn current_evaluation(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let evaluation = scope
+        .get_slot::<IsoState>()
+        .expect("no state bound")
+        .evaluation;
+    let v = match evaluation {
+        Some(e) => ScriptValue::Object(vec![
+            ("score", ScriptValue::Number(e.score as _)),
+            ("exact", ScriptValue::Bool(e.exact)),
+            ("depth", ScriptValue::Number(e.depth as _)),
+            ("advantage", ScriptValue::Number(e.advantage() as _)),
+        ]),
+        None => ScriptValue::Null,
+    };
+    let v = v.to_v8(scope);
+    rv.set(v);
+}
+/// `newGame()`: abandons the script and runs it again on a new game after
+/// the current frame
+fn new_game(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    scope
+        .get_slot_mut::<IsoState>()
+        .expect("no state bound")
+        .new_game_requested = true;
+}
+/// `gameClock()`: `{ black, white, running, paused }` with
+/// `{ remainingMs, byoyomi }` of each side, or null without a time control.
+/// `remainingMs` is of the byoyomi period once the main time ran out.
+fn game_clock(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let state = scope.get_slot::<IsoState>().expect("no state bound");
+    let v = match &state.clock {
+        Some(clock) => {
+            let side = |color| {
+                let s = clock.side(color);
+                ScriptValue::Object(vec![
+                    ("remainingMs", ScriptValue::Number(s.remaining_ms())),
+                    ("byoyomi", ScriptValue::Bool(s.in_byoyomi())),
+                ])
+            };
+            let paused = clock.running().is_some()
+                && state.clock_pauses_for_animations
+                && state.tweens.is_active();
+            ScriptValue::Object(vec![
+                ("black", side(Color::Black)),
+                ("white", side(Color::White)),
+                (
+                    "running",
+                    clock.running().map_or(ScriptValue::Null, |c| {
+                        ScriptValue::String(c.name().to_owned())
+                    }),
+                ),
+                ("paused", ScriptValue::Bool(paused)),
+            ])
+        }
+        None => ScriptValue::Null,
+    };
+    let v = v.to_v8(scope);
+    rv.set(v);
+}
+/// `playMove(x, y)`: places a disc for the side to move and returns the
+/// flipped cells. Throws for illegal moves.
+fn play_move(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let board_size = scope
+        .get_slot::<IsoState>()
+        .expect("no state bound")
+        .board_size;
+    let xy = |n: usize| ((n % board_size) as u32, (n / board_size) as u32);
+    let cell = args.get(0).integer_value(scope).and_then(|x| {
+        let y = args.get(1).integer_value(scope)?;
+        cell_index(board_size, x, y)
+    });
+    let (x, y) = match cell {
+        Some(n) => xy(n),
+        None => return throw_type_error(scope, "cell out of the board"),
+    };
+    let state = scope.get_slot_mut::<IsoState>().expect("no state bound");
+    let events = match state.game.play(x, y) {
+        Ok(e) => e,
+        Err(e) => return throw_type_error(scope, &e),
+    };
+    let flipped = match &events[0] {
+        GameEvent::Placed { flipped, .. } => flipped.clone(),
+        _ => unreachable!("a move starts with Placed"),
+    };
+    state.push_game_events(events);
+    let v =
+        ScriptValue::Array(flipped.into_iter().map(ScriptValue::xy).collect())
+            .to_v8(scope);
+    rv.set(v);
+}
+/// `resign(color)`: ends the game as a loss for `color`
+fn resign(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let color = match v8::Local::<v8::String>::try_from(args.get(0)) {
+        Ok(s) => s.to_rust_string_lossy(scope),
+        Err(e) => return throw_type_error(scope, &e.to_string()),
+    };
+    let color = match Color::parse(&color) {
+        Some(c) => c,
+        None => return throw_type_error(scope, "color must be black or white"),
+    };
+    let state = scope.get_slot_mut::<IsoState>().expect("no state bound");
+    match state.game.resign(color) {
+        Ok(events) => state.push_game_events(events),
+        Err(e) => throw_type_error(scope, &e),
+    }
+}
+/// `onGameEvent(callback)`: calls back with every game event, in order at
+/// the start of frames
+fn on_game_event(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let f = match v8::Local::<v8::Function>::try_from(args.get(0)) {
+        Ok(f) => v8::Global::new(scope, f),
+        Err(e) => return throw_type_error(scope, &e.to_string()),
+    };
+    scope
+        .get_slot_mut::<IsoState>()
+        .expect("no state bound")
+        .game_event_callbacks
+        .push(f);
+}
+/// `undoMove()`: the last played move or pass, taken back by the game and
+/// to be animated by the script; null at the start of the game
+fn undo_move(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let state = scope.get_slot_mut::<IsoState>().expect("no state bound");
+    let entry = state.history.undo().map(ScriptValue::history_entry);
+    if entry.is_some() {
+        state.replay_game();
+    }
+    let v = entry.unwrap_or(ScriptValue::Null).to_v8(scope);
+    rv.set(v);
+}
+/// `redoMove()`: the last undone move or pass, played again by the game and
+/// to be animated by the script; null if there is none
+fn redo_move(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let state = scope.get_slot_mut::<IsoState>().expect("no state bound");
+    let entry = state.history.redo().cloned();
+    if let Some((x, y)) = entry.as_ref().and_then(|e| e.cell) {
+        // recorded already, passes follow from the rules
+        let events = state.game.play(x, y).expect("illegal move to redo");
+        state.queue_game_events(events);
+    }
+    let v = entry
+        .as_ref()
+        .map_or(ScriptValue::Null, ScriptValue::history_entry)
+        .to_v8(scope);
+    rv.set(v);
+}
+/// `moveHistory()`: moves played so far, oldest first
+fn move_history(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let entries = scope
+        .get_slot::<IsoState>()
+        .expect("no state bound")
+        .history
+        .played()
+        .iter()
+        .map(ScriptValue::history_entry)
+        .collect();
+    let v = ScriptValue::Array(entries).to_v8(scope);
+    rv.set(v);
+}
+/// `takeHistoryCommand()`: "undo" or "redo" requested by keys, oldest first,
+/// or null
+fn take_history_command(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let commands = &mut scope
+        .get_slot_mut::<IsoState>()
+        .expect("no state bound")
+        .history_commands;
+    let command = if commands.is_empty() {
+        None
+    } else {
+        Some(commands.remove(0))
+    };
+    let v: v8::Local<v8::Value> = match command {
+        Some(c) => v8::String::new(scope, c.name())
+            .expect("Failed to create command name")
+            .into(),
+        None => v8::null(scope).into(),
+    };
+    rv.set(v);
+}
+/// `saveGame(path)`: writes the moves played so far as GGF for `.ggf` paths,
+/// as a transcript otherwise (8x8 only)
+fn save_game(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let path = match v8::Local::<v8::String>::try_from(args.get(0)) {
+        Ok(s) => s.to_rust_string_lossy(scope),
+        Err(e) => return throw_type_error(scope, &e.to_string()),
+    };
+    let state = scope.get_slot::<IsoState>().expect("no state bound");
+    if state.board_size != bitboard::SIZE {
+        return throw_type_error(
+            scope,
+            "game records are supported only on 8x8 boards",
+        );
+    }
+    let name = |c| {
+        if state.ai_color == Some(c) {
+            "engine"
+        } else {
+            "human"
+        }
+    };
+    let (start, start_color) = state.start_position;
+    let result =
+        GameRecord::from_history(start, start_color, state.history.played())
+            .save(&path, name(Color::Black), name(Color::White));
+    if let Err(e) = result {
+        throw_type_error(scope, &e);
+    }
+}
+/// `loadGame(path)`: reads a transcript or GGF file (by extension) for
+/// `takeLoadedGame`
+fn load_game(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _rv: v8::ReturnValue,
+) {
+    let path = match v8::Local::<v8::String>::try_from(args.get(0)) {
+        Ok(s) => s.to_rust_string_lossy(scope),
+        Err(e) => return throw_type_error(scope, &e.to_string()),
+    };
+    let result = scope
+        .get_slot_mut::<IsoState>()
+        .expect("no state bound")
+        .load_game(&path);
+    if let Err(e) = result {
+        throw_type_error(scope, &e);
+    }
+}
+/// `takeLoadedGame()`: `{ start, moves }` of a game loaded by `loadGame`,
+/// `--load` or a dropped file, or null. The history is replaced with the
+/// moves, and the script is expected to restore the board.
+fn take_loaded_game(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let state = scope.get_slot_mut::<IsoState>().expect("no state bound");
+    let v = match state.loaded_game.take() {
+        Some(record) => {
+            let entries = record.history_entries(state.current_time_ms);
+            state.history = MoveHistory::new();
+            for e in &entries {
+                state.history.record(e.clone());
+            }
+            state.start_position = (record.start, record.start_color);
+            state.replay_game();
+            ScriptValue::Object(vec![
+                (
+                    "start",
+                    ScriptValue::start_position(
+                        &record.start,
+                        record.start_color,
+                    ),
+                ),
+                (
+                    "moves",
+                    ScriptValue::Array(
+                        entries
+                            .iter()
+                            .map(ScriptValue::history_entry)
+                            .collect(),
+                    ),
+                ),
+            ])
+        }
+        None => ScriptValue::Null,
+    };
+    let v = v.to_v8(scope);
+    rv.set(v);
+}
+/// `startPosition()`: position the current game started from as
+/// `{ black, white, toMove }`, null on boards other than 8x8
+fn start_position(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let state = scope.get_slot::<IsoState>().expect("no state bound");
+    let v = if state.board_size == bitboard::SIZE {
+        let (position, to_move) = state.start_position;
+        ScriptValue::start_position(&position, to_move)
+    } else {
+        ScriptValue::Null
+    };
+    let v = v.to_v8(scope);
+    rv.set(v);
+}
+fn ai_color(
+    scope: &mut v8::HandleScope,
+    _args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let color = scope
+        .get_slot::<IsoState>()
+        .expect("no state bound")
+        .ai_color;
+    let v: v8::Local<v8::Value> = match color {
+        Some(c) => v8::String::new(scope, c.name())
+            .expect("Failed to create color name")
+            .into(),
+        None => v8::null(scope).into(),
+    };
+    rv.set(v);
+}
+/// `requestAiMove(color, budgetMs)`: searches the current board state for
+/// `color` and resolves to `[x, y]`, or null if the side has to pass
+fn request_ai_move(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let color = match v8::Local::<v8::String>::try_from(args.get(0)) {
+        Ok(s) => s.to_rust_string_lossy(scope),
+        Err(e) => return throw_type_error(scope, &e.to_string()),
+    };
+    let color = match Color::parse(&color) {
+        Some(c) => c,
+        None => return throw_type_error(scope, "color must be black or white"),
+    };
+    let budget_ms = match v8::Local::<v8::Number>::try_from(args.get(1)) {
+        Ok(v) => v.value().max(0.0),
+        Err(e) => return throw_type_error(scope, &e.to_string()),
+    };
+    let position = match engine_position(scope, color) {
+        Ok(p) => p,
+        Err(e) => return throw_type_error(scope, e),
+    };
+
+    let (id, promise) = new_pending_promise(scope);
+    scope
+        .get_slot_mut::<IsoState>()
+        .expect("no state bound")
+        .search_requests
+        .push(SearchRequest {
+            id,
+            position,
+            kind: SearchKind::BestMove(std::time::Duration::from_secs_f64(
+                budget_ms / 1000.0,
+            )),
+        });
+    rv.set(promise.into());
+}
+/// `solveEndgame(color)`: resolves to `{ move, score }` with the exact final
+/// disc differential of `color` and its best move (null to pass)
+fn solve_endgame(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut rv: v8::ReturnValue,
+) {
+    let color = match v8::Local::<v8::String>::try_from(args.get(0)) {
+        Ok(s) => s.to_rust_string_lossy(scope),
+        Err(e) => return throw_type_error(scope, &e.to_string()),
+    };
+    let color = match Color::parse(&color) {
+        Some(c) => c,
+        None => return throw_type_error(scope, "color must be black or white"),
+    };
+    let position = match engine_position(scope, color) {
+        Ok(p) => p,
+        Err(e) => return throw_type_error(scope, e),
+    };
+    if position.empties().count_ones() > endgame::MAX_SOLVE_EMPTIES {
+        return throw_type_error(scope, "too many empty cells to solve");
+    }
+
+    let (id, promise) = new_pending_promise(scope);
+    scope
+        .get_slot_mut::<IsoState>()
+        .expect("no state bound")
+        .search_requests
+        .push(SearchRequest {
+            id,
+            position,
+            kind: SearchKind::Solve,
+        });
+    rv.set(promise.into());
+}
+
+fn register_global_function(
+    scope: &mut v8::HandleScope,
+    global: v8::Local<v8::Object>,
+    name: &str,
+    callback: impl v8::MapFnTo<v8::FunctionCallback>,
+) {
+    let name_str = v8::String::new(scope, name)
+        .expect("Failed to create function name object");
+    let func = v8::FunctionTemplate::new(scope, callback)
+        .get_function(scope)
+        .unwrap_or_else(|| panic!("Failed to get {} function", name));
+    global.set(scope, name_str.into(), func.into());
+}
+
+/// Values passed from Rust to scripts
+pub enum ScriptValue {
+    Undefined,
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<ScriptValue>),
+    Object(Vec<(&'static str, ScriptValue)>),
+}
+impl ScriptValue {
+    /// `[x, y]` of a cell index on the engine board, null for None
+    fn cell(sq: Option<u32>) -> Self {
+        match sq {
+            Some(sq) => {
+                let (x, y) = bitboard::square_xy(sq);
+                ScriptValue::Array(vec![
+                    ScriptValue::Number(x as _),
+                    ScriptValue::Number(y as _),
+                ])
+            }
+            None => ScriptValue::Null,
+        }
+    }
+
+    /// `[x, y]` of a cell on a board of any size
+    fn xy((x, y): (u32, u32)) -> Self {
+        ScriptValue::Array(vec![
+            ScriptValue::Number(x as _),
+            ScriptValue::Number(y as _),
+        ])
+    }
+
+    /// `{ type, ... }` as declared for `onGameEvent` in the script
+    fn game_event(e: &GameEvent) -> Self {
+        let color = |c: &Color| ScriptValue::String(c.name().to_owned());
+        let cells = |cells: &[(u32, u32)]| {
+            ScriptValue::Array(cells.iter().copied().map(Self::xy).collect())
+        };
+        let kind = |name: &str| ScriptValue::String(name.to_owned());
+        match e {
+            GameEvent::Placed {
+                color: c,
+                cell,
+                flipped,
+            } => ScriptValue::Object(vec![
+                ("type", kind("placed")),
+                ("color", color(c)),
+                ("move", Self::xy(*cell)),
+                ("flipped", cells(flipped)),
+            ]),
+            GameEvent::Passed { color: c } => ScriptValue::Object(vec![
+                ("type", kind("pass")),
+                ("color", color(c)),
+            ]),
+            GameEvent::Turn {
+                color: c,
+                legal_moves,
+            } => ScriptValue::Object(vec![
+                ("type", kind("turn")),
+                ("color", color(c)),
+                ("legalMoves", cells(legal_moves)),
+            ]),
+            GameEvent::Ended(r) => ScriptValue::Object(vec![
+                ("type", kind("end")),
+                ("winner", r.winner.as_ref().map_or(ScriptValue::Null, color)),
+                ("black", ScriptValue::Number(r.black as _)),
+                ("white", ScriptValue::Number(r.white as _)),
+                ("reason", kind(r.reason.name())),
+            ]),
+        }
+    }
+
+    /// `{ black, white, toMove }` with `[x, y]` cells of each color
+    fn start_position(pos: &Position, to_move: Color) -> Self {
+        let (black, white) = pos.discs(to_move);
+        let cells = |b| {
+            ScriptValue::Array(
+                bitboard::squares(b)
+                    .map(|sq| Self::cell(Some(sq)))
+                    .collect(),
+            )
+        };
+        ScriptValue::Object(vec![
+            ("black", cells(black)),
+            ("white", cells(white)),
+            ("toMove", ScriptValue::String(to_move.name().to_owned())),
+        ])
+    }
+
+    /// `{ color, move, flipped, timeMs }` with `[x, y]` cells, null move for
+    /// a pass
+    fn history_entry(e: &HistoryEntry) -> Self {
+        ScriptValue::Object(vec![
+            ("color", ScriptValue::String(e.color.name().to_owned())),
+            ("move", e.cell.map_or(ScriptValue::Null, Self::xy)),
+            (
+                "flipped",
+                ScriptValue::Array(
+                    e.flipped.iter().copied().map(Self::xy).collect(),
+                ),
+            ),
+            ("timeMs", ScriptValue::Number(e.time_ms)),
+        ])
+    }
+
+    fn to_v8<'s>(
+        &self,
+        scope: &mut v8::HandleScope<'s>,
+    ) -> v8::Local<'s, v8::Value> {
+        match self {
+            ScriptValue::Undefined => v8::undefined(scope).into(),
+            ScriptValue::Null => v8::null(scope).into(),
+            ScriptValue::Bool(v) => v8::Boolean::new(scope, *v).into(),
+            ScriptValue::Number(v) => v8::Number::new(scope, *v).into(),
+            ScriptValue::String(v) => v8::String::new(scope, v)
+                .expect("Failed to create string")
+                .into(),
+            ScriptValue::Array(values) => {
+                let elements: Vec<_> =
+                    values.iter().map(|v| v.to_v8(scope)).collect();
+                v8::Array::new_with_elements(scope, &elements).into()
+            }
+            ScriptValue::Object(properties) => {
+                let obj = v8::Object::new(scope);
+                for (name, v) in properties {
+                    let key = v8::String::new(scope, name)
+                        .expect("Failed to create property name");
+                    let v = v.to_v8(scope);
+                    obj.set(scope, key.into(), v);
+                }
+                obj.into()
+            }
+        }
+    }
+}
+
+/// Runs `f` with the contents of a backing store (empty if none or detached)
+fn with_backing_store_bytes<R>(
+    bs: Option<&v8::SharedRef<v8::BackingStore>>,
+    f: impl FnOnce(&mut [u8]) -> R,
+) -> R {
+    match bs.filter(|bs| bs.byte_length() > 0) {
+        // only touched from the isolate's thread while no script is running
+        Some(bs) => f(unsafe {
+            std::slice::from_raw_parts_mut(
+                bs.data() as *mut u8,
+                bs.byte_length(),
+            )
+        }),
+        None => f(&mut []),
+    }
+}
+
+pub struct ScriptEngine {
+    // Note: Inspectors must be destroyed before isolate destruction
+    _inspector: v8::UniqueRef<v8::inspector::V8Inspector>,
+    _inspector_client: Box<ScriptInspectorClient>,
+    iso: v8::OwnedIsolate,
+    context: v8::Global<v8::Context>,
+}
+impl ScriptEngine {
+    pub fn new(
+        board_size: usize,
+        ai_color: Option<Color>,
+        book: Option<Arc<Book>>,
+    ) -> Self {
+        let platform = v8::new_default_platform().unwrap();
+        v8::V8::initialize_platform(platform);
+        v8::V8::initialize();
+
+        let mut iso = v8::Isolate::new(v8::CreateParams::default());
+        iso.set_slot(IsoState::new(board_size, ai_color, book));
+        let mut inspector_client = Box::new(ScriptInspectorClient::new());
+        let mut inspector = v8::inspector::V8Inspector::create(
+            &mut iso,
+            &mut *inspector_client,
+        );
+        let context = {
+            let mut scope = v8::HandleScope::new(&mut iso);
+            let context = v8::Context::new(&mut scope);
             let mut scope = v8::ContextScope::new(&mut scope, context);
 
             inspector.context_created(
@@ -665,39 +2204,162 @@ impl ScriptEngine {
 
             // register global exposures
             let global = context.global(&mut scope);
-            let name = v8::String::new(&mut scope, "requestNextFrame")
-                .expect("Failed to create function name object");
-            let func =
-                v8::FunctionTemplate::new(&mut scope, request_next_frame)
-                    .get_function(&mut scope)
-                    .expect("Failed to get requestNextFrame function");
-            global.set(&mut scope, name.into(), func.into());
-            let name = v8::String::new(&mut scope, "isButtonPressing")
-                .expect("Failed to create function name object");
-            let func =
-                v8::FunctionTemplate::new(&mut scope, is_button_pressing)
-                    .get_function(&mut scope)
-                    .expect("Failed to get isButtonPressing function");
-            global.set(&mut scope, name.into(), func.into());
-            let name = v8::String::new(&mut scope, "cursorPos")
-                .expect("Failed to create function name object");
-            let func = v8::FunctionTemplate::new(&mut scope, cursor_pos)
-                .get_function(&mut scope)
-                .expect("Failed to get cursorPos function");
-            global.set(&mut scope, name.into(), func.into());
-            let name = v8::String::new(&mut scope, "setBoardStateBuffer")
-                .expect("Failed to create function name object");
-            let func =
-                v8::FunctionTemplate::new(&mut scope, set_board_state_buffer)
-                    .get_function(&mut scope)
-                    .expect("Failed to get setBoardStateBuffer function");
-            global.set(&mut scope, name.into(), func.into());
-            let name = v8::String::new(&mut scope, "currentTimeMs")
-                .expect("Failed to create function name object");
-            let func = v8::FunctionTemplate::new(&mut scope, current_time_ms)
-                .get_function(&mut scope)
-                .expect("Failed to get currentTimeMs function");
-            global.set(&mut scope, name.into(), func.into());
+            register_global_function(
+                &mut scope,
+                global,
+                "requestNextFrame",
+                request_next_frame,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "isButtonPressing",
+                is_button_pressing,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "cursorPos",
+                cursor_pos,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "setBoardStateBuffer",
+                set_board_state_buffer,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "boardSize",
+                board_size,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "currentTimeMs",
+                current_time_ms,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "emitParticles",
+                emit_particles,
+            );
+            register_global_function(&mut scope, global, "setTheme", set_theme);
+            register_global_function(&mut scope, global, "drawRect", draw_rect);
+            register_global_function(
+                &mut scope,
+                global,
+                "drawRoundedRect",
+                draw_rounded_rect,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "drawCircle",
+                draw_circle,
+            );
+            register_global_function(&mut scope, global, "drawLine", draw_line);
+            register_global_function(
+                &mut scope,
+                global,
+                "drawImage",
+                draw_image,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "loadTexture",
+                load_texture,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "playSound",
+                play_sound,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "animateCells",
+                animate_cells,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "animateFlip",
+                animate_flip,
+            );
+            register_global_function(&mut scope, global, "wait", wait);
+            register_global_function(&mut scope, global, "aiColor", ai_color);
+            register_global_function(
+                &mut scope,
+                global,
+                "requestAiMove",
+                request_ai_move,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "solveEndgame",
+                solve_endgame,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "bookMoves",
+                book_moves,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "currentEvaluation",
+                current_evaluation,
+            );
+            register_global_function(&mut scope, global, "playMove", play_move);
+            register_global_function(&mut scope, global, "resign", resign);
+            register_global_function(
+                &mut scope,
+                global,
+                "gameClock",
+                game_clock,
+            );
+            register_global_function(&mut scope, global, "newGame", new_game);
+            register_global_function(
+                &mut scope,
+                global,
+                "onGameEvent",
+                on_game_event,
+            );
+            register_global_function(&mut scope, global, "undoMove", undo_move);
+            register_global_function(&mut scope, global, "redoMove", redo_move);
+            register_global_function(
+                &mut scope,
+                global,
+                "moveHistory",
+                move_history,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "takeHistoryCommand",
+                take_history_command,
+            );
+            register_global_function(&mut scope, global, "saveGame", save_game);
+            register_global_function(&mut scope, global, "loadGame", load_game);
+            register_global_function(
+                &mut scope,
+                global,
+                "takeLoadedGame",
+                take_loaded_game,
+            );
+            register_global_function(
+                &mut scope,
+                global,
+                "startPosition",
+                start_position,
+            );
 
             v8::Global::new(&mut scope, context)
         };
@@ -729,6 +2391,232 @@ impl ScriptEngine {
             .current_time_ms = t.as_nanos() as f64 / 1_000_000.0;
     }
 
+    pub fn take_particle_emissions(&mut self) -> Vec<(f64, f64, String)> {
+        std::mem::replace(
+            &mut self
+                .iso
+                .get_slot_mut::<IsoState>()
+                .expect("no state bound")
+                .particle_emissions,
+            Vec::new(),
+        )
+    }
+
+    pub fn take_requested_theme(&mut self) -> Option<String> {
+        self.iso
+            .get_slot_mut::<IsoState>()
+            .expect("no state bound")
+            .requested_theme
+            .take()
+    }
+
+    /// Backing store of the board state buffer set by the script
+    fn board_state_backing_store(
+        &mut self,
+    ) -> Option<v8::SharedRef<v8::BackingStore>> {
+        let mut scope =
+            v8::HandleScope::with_context(&mut self.iso, &self.context);
+        let buffer = scope
+            .get_slot_mut::<IsoState>()
+            .expect("no state bound")
+            .board_state_buffer
+            .take()?;
+        let bs = v8::Local::new(&mut scope, &buffer).get_backing_store();
+        scope
+            .get_slot_mut::<IsoState>()
+            .expect("no state bound")
+            .board_state_buffer = Some(buffer);
+
+        Some(bs)
+    }
+
+    /// Runs `f` with the contents of the board state buffer if it has been
+    /// modified since the last call
+    pub fn upload_board_state(&mut self, f: impl FnOnce(&[u8])) {
+        let state =
+            self.iso.get_slot_mut::<IsoState>().expect("no state bound");
+        if !std::mem::replace(&mut state.board_state_dirty, false) {
+            return;
+        }
+        if let Some(bs) = self.board_state_backing_store() {
+            with_backing_store_bytes(Some(&bs), |b| f(b));
+        }
+    }
+
+    /// Advances tweens and resolves promises of finished animations
+    pub fn update_tweens(&mut self) {
+        let state = self.iso.get_slot::<IsoState>().expect("no state bound");
+        if !state.tweens.is_active() {
+            return;
+        }
+        let now_ms = state.current_time_ms;
+
+        let bs = self.board_state_backing_store();
+        let state =
+            self.iso.get_slot_mut::<IsoState>().expect("no state bound");
+        let finished = with_backing_store_bytes(bs.as_ref(), |b| {
+            state.tweens.finish(now_ms, b)
+        });
+        state.board_state_dirty = true;
+        for id in finished {
+            self.resolve_promise(id, ScriptValue::Undefined);
+        }
+
+        // resolved continuations may have started new tweens or set a buffer
+        let bs = self.board_state_backing_store();
+        let tweens = &self
+            .iso
+            .get_slot::<IsoState>()
+            .expect("no state bound")
+            .tweens;
+        with_backing_store_bytes(bs.as_ref(), |b| tweens.apply(now_ms, b));
+    }
+
+    /// Resolves a promise created by `new_pending_promise` and runs the
+    /// continuations
+    pub fn resolve_promise(&mut self, id: u32, value: ScriptValue) {
+        let resolver = match self
+            .iso
+            .get_slot_mut::<IsoState>()
+            .expect("no state bound")
+            .pending_promises
+            .remove(&id)
+        {
+            Some(r) => r,
+            None => return,
+        };
+
+        let mut scope =
+            v8::HandleScope::with_context(&mut self.iso, &self.context);
+        let value = value.to_v8(&mut scope);
+        resolver.get(&mut scope).resolve(&mut scope, value);
+        scope.perform_microtask_checkpoint();
+    }
+
+    /// Game position with the side to move, None on boards other than 8x8
+    pub fn board_position(&mut self) -> Option<(Position, Color)> {
+        self.iso
+            .get_slot::<IsoState>()
+            .expect("no state bound")
+            .game
+            .engine_position()
+    }
+
+    pub fn set_evaluation(&mut self, evaluation: Option<Evaluation>) {
+        self.iso
+            .get_slot_mut::<IsoState>()
+            .expect("no state bound")
+            .evaluation = evaluation;
+    }
+
+    /// Starts the game from `position` instead of the initial one. Must be
+    /// called before the script runs.
+    pub fn set_start_position(&mut self, position: Position, to_move: Color) {
+        self.iso
+            .get_slot_mut::<IsoState>()
+            .expect("no state bound")
+            .initial_position = (position, to_move);
+        self.iso
+            .get_slot_mut::<IsoState>()
+            .expect("no state bound")
+            .restart_game();
+    }
+
+    pub fn request_new_game(&mut self) {
+        self.iso
+            .get_slot_mut::<IsoState>()
+            .expect("no state bound")
+            .new_game_requested = true;
+    }
+
+    pub fn take_new_game_request(&mut self) -> bool {
+        std::mem::replace(
+            &mut self
+                .iso
+                .get_slot_mut::<IsoState>()
+                .expect("no state bound")
+                .new_game_requested,
+            false,
+        )
+    }
+
+    /// Starts a new game by running `code` again in the same context once
+    /// the old script is detached. The board state buffer is dropped, so the
+    /// uniform buffer has to be reset by the caller.
+    pub fn new_session(&mut self, code: &str) {
+        self.iso
+            .get_slot_mut::<IsoState>()
+            .expect("no state bound")
+            .reset_session();
+        self.execute_code(code);
+    }
+
+    /// Runs clocks from the current turn on
+    pub fn set_time_control(
+        &mut self,
+        control: TimeControl,
+        pause_for_animations: bool,
+    ) {
+        let state =
+            self.iso.get_slot_mut::<IsoState>().expect("no state bound");
+        state.time_control = Some(control);
+        state.clock_pauses_for_animations = pause_for_animations;
+        let mut clock = GameClock::new(control);
+        if let Some(color) = state.game.to_move() {
+            clock.start(color, state.current_time_ms);
+        }
+        state.clock = Some(clock);
+    }
+
+    /// Queues a game record for the script to restore
+    pub fn load_game(&mut self, path: &str) -> Result<(), String> {
+        self.iso
+            .get_slot_mut::<IsoState>()
+            .expect("no state bound")
+            .load_game(path)
+    }
+
+    pub fn request_history_command(&mut self, command: HistoryCommand) {
+        self.iso
+            .get_slot_mut::<IsoState>()
+            .expect("no state bound")
+            .history_commands
+            .push(command);
+    }
+
+    pub fn take_search_requests(&mut self) -> Vec<SearchRequest> {
+        std::mem::replace(
+            &mut self
+                .iso
+                .get_slot_mut::<IsoState>()
+                .expect("no state bound")
+                .search_requests,
+            Vec::new(),
+        )
+    }
+
+    pub fn take_sound_requests(&mut self) -> Vec<(String, PlayOptions)> {
+        std::mem::replace(
+            &mut self
+                .iso
+                .get_slot_mut::<IsoState>()
+                .expect("no state bound")
+                .sound_requests,
+            Vec::new(),
+        )
+    }
+
+    pub fn clear_draw_list(&mut self) {
+        self.draw_list_mut().clear();
+    }
+    pub fn draw_list_mut(&mut self) -> &mut DrawList {
+        &mut self
+            .iso
+            .get_slot_mut::<IsoState>()
+            .expect("no state bound")
+            .draw_list
+    }
+
     pub fn execute_code(&mut self, code: &str) {
         let mut scope =
             v8::HandleScope::with_context(&mut self.iso, &self.context);
@@ -751,7 +2639,32 @@ impl ScriptEngine {
         }
     }
 
+    /// Calls `onGameEvent` subscribers with the queued events
+    fn dispatch_game_events(&mut self) {
+        let state =
+            self.iso.get_slot_mut::<IsoState>().expect("no state bound");
+        let events = std::mem::replace(&mut state.game_events, Vec::new());
+        let callbacks = state.game_event_callbacks.clone();
+
+        let mut scope =
+            v8::HandleScope::with_context(&mut self.iso, &self.context);
+        let global = self.context.get(&mut scope).global(&mut scope);
+        for e in &events {
+            let v = ScriptValue::game_event(e).to_v8(&mut scope);
+            for cb in &callbacks {
+                cb.get(&mut scope)
+                    .call(&mut scope, global.into(), &[v])
+                    .expect("Failed to callback to game event");
+            }
+        }
+    }
+
     pub fn next_frame(&mut self) {
+        self.iso
+            .get_slot_mut::<IsoState>()
+            .expect("no state bound")
+            .tick_clock();
+        self.dispatch_game_events();
         let callbacks = std::mem::replace(
             &mut self
                 .iso
@@ -0,0 +1,211 @@
+use crate::audio::OutputKind;
+use crate::bitboard::{Color, Position};
+use crate::book::BOOK_PATH;
+use crate::cell_state::BOARD_SIZES;
+use crate::clock::TimeControl;
+use crate::gl_debug::Severity;
+use crate::position_codec::parse_position;
+
+/// Subcommands run instead of the game
+pub enum Command {
+    /// `solve <position> [black|white]`: prints the exact endgame result
+    Solve { position: Position, to_move: Color },
+    /// `show-position <position>`: prints the position in every format
+    ShowPosition { position: Position, to_move: Color },
+    /// `build-book <transcripts> <output>`: writes an opening book built from
+    /// game transcripts
+    BuildBook { transcripts: String, output: String },
+}
+
+/// Command line options
+pub struct AppOptions {
+    /// MSAA sample count (0 = disabled)
+    pub msaa_samples: u32,
+    /// Renders into an offscreen multisampled framebuffer and resolves it
+    /// before presenting, instead of requesting a multisampled window
+    pub offscreen_msaa: bool,
+    /// Creates a debug context and logs KHR_debug messages
+    pub gl_debug: bool,
+    /// Minimum severity of logged debug messages
+    pub gl_debug_severity: Severity,
+    /// Panics on high severity GL errors (for development)
+    pub gl_debug_panic: bool,
+    /// Built-in theme name or path to a theme file
+    pub theme: Option<String>,
+    /// Where mixed sounds go (`null`, `wav:<path>` or `device`)
+    pub audio_output: OutputKind,
+    /// Cells in a row, one of `BOARD_SIZES`
+    pub board_size: usize,
+    /// Side played by the built-in engine
+    pub ai_color: Option<Color>,
+    /// Opening book used by the engine, ignored if missing
+    pub book_path: String,
+    /// Transcript or GGF file restored at startup (8x8 only)
+    pub load_path: Option<String>,
+    /// Position the game starts from instead of the initial one (8x8 only)
+    pub start_position: Option<(Position, Color)>,
+    /// Clocks of both sides, untimed if None
+    pub time_control: Option<TimeControl>,
+    /// Stops the clock while moves are animated
+    pub clock_pauses_for_animations: bool,
+    pub command: Option<Command>,
+}
+impl AppOptions {
+    pub fn from_args() -> Self {
+        let mut opts = AppOptions {
+            msaa_samples: 0,
+            offscreen_msaa: false,
+            gl_debug: false,
+            gl_debug_severity: Severity::Medium,
+            gl_debug_panic: false,
+            theme: None,
+            audio_output: OutputKind::default(),
+            board_size: 8,
+            ai_color: None,
+            book_path: BOOK_PATH.to_owned(),
+            load_path: None,
+            start_position: None,
+            time_control: None,
+            clock_pauses_for_animations: false,
+            command: None,
+        };
+
+        let mut args = std::env::args().skip(1).peekable();
+        while let Some(a) = args.next() {
+            match a.as_str() {
+                "--msaa" => {
+                    opts.msaa_samples = args
+                        .next()
+                        .and_then(|s| s.parse().ok())
+                        .expect("--msaa requires a sample count");
+                }
+                "--offscreen-msaa" => {
+                    opts.offscreen_msaa = true;
+                }
+                "--gl-debug" => {
+                    opts.gl_debug = true;
+                }
+                "--gl-debug-severity" => {
+                    opts.gl_debug_severity =
+                        args.next().and_then(|s| Severity::parse(&s)).expect(
+                            "--gl-debug-severity requires one of \
+                             high, medium, low or notification",
+                        );
+                }
+                "--gl-debug-panic" => {
+                    opts.gl_debug = true;
+                    opts.gl_debug_panic = true;
+                }
+                "--theme" => {
+                    opts.theme = Some(
+                        args.next().expect("--theme requires a name or path"),
+                    );
+                }
+                "--audio" => {
+                    opts.audio_output = args
+                        .next()
+                        .and_then(|s| OutputKind::parse(&s))
+                        .expect("--audio requires null, wav:<path> or device");
+                }
+                "--board-size" => {
+                    opts.board_size = args
+                        .next()
+                        .and_then(|s| s.parse().ok())
+                        .filter(|n| BOARD_SIZES.contains(n))
+                        .expect("--board-size requires 6, 8, 10 or 12");
+                }
+                "--ai" => {
+                    opts.ai_color = Some(
+                        args.next()
+                            .and_then(|s| Color::parse(&s))
+                            .expect("--ai requires black or white"),
+                    );
+                }
+                "solve" if opts.command.is_none() => {
+                    let (position, to_move) = position_arg(
+                        args.next().expect("solve requires a position"),
+                    );
+                    // overrides the side to move implied by a grid
+                    let (position, to_move) =
+                        match args.peek().and_then(|s| Color::parse(s)) {
+                            Some(c) => {
+                                args.next();
+                                let (black, white) = position.discs(to_move);
+                                (Position::from_discs(black, white, c), c)
+                            }
+                            None => (position, to_move),
+                        };
+                    opts.command = Some(Command::Solve { position, to_move });
+                }
+                "show-position" if opts.command.is_none() => {
+                    let (position, to_move) = position_arg(
+                        args.next().expect("show-position requires a position"),
+                    );
+                    opts.command =
+                        Some(Command::ShowPosition { position, to_move });
+                }
+                "--position" => {
+                    opts.start_position = Some(position_arg(
+                        args.next().expect("--position requires a position"),
+                    ));
+                }
+                "--time" => {
+                    opts.time_control = Some(
+                        args.next()
+                            .and_then(|s| TimeControl::parse(&s))
+                            .expect(
+                                "--time requires <main>[+<increment>]\
+                                 [/<byoyomi>] in seconds",
+                            ),
+                    );
+                }
+                "--pause-clock" => {
+                    opts.clock_pauses_for_animations = true;
+                }
+                "--book" => {
+                    opts.book_path =
+                        args.next().expect("--book requires a path");
+                }
+                "--load" => {
+                    opts.load_path =
+                        Some(args.next().expect("--load requires a path"));
+                }
+                "build-book" if opts.command.is_none() => {
+                    let transcripts = args
+                        .next()
+                        .expect("build-book requires a transcript file");
+                    let output = args
+                        .next()
+                        .expect("build-book requires an output path");
+                    opts.command = Some(Command::BuildBook {
+                        transcripts,
+                        output,
+                    });
+                }
+                _ => panic!("Unknown option: {}", a),
+            }
+        }
+
+        opts
+    }
+
+    /// Sample count requested on the window's default framebuffer
+    pub fn window_samples(&self) -> u32 {
+        if self.offscreen_msaa {
+            0
+        } else {
+            self.msaa_samples
+        }
+    }
+}
+
+/// A position in any format of `position_codec`, or a file containing one
+fn position_arg(arg: String) -> (Position, Color) {
+    let text = if std::path::Path::new(&arg).is_file() {
+        std::fs::read_to_string(&arg)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", arg, e))
+    } else {
+        arg
+    };
+    parse_position(&text).unwrap_or_else(|e| panic!("{}", e))
+}
@@ -0,0 +1,266 @@
+//! Particle bursts simulated on the CPU and drawn as instanced quads
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::glres::{
+    ArrayBufferTarget, Buffer, GlObject, VertexArray, ARRAY_BUFFER,
+};
+
+pub const PRESETS_PATH: &'static str = "./assets/particles.json";
+
+/// Distance between cell centers of the 8x8 board in world space
+/// (`SPACE` in stone_common.glsl). Larger boards are scaled to the same size.
+const CELL_SPACE_8X8: f32 = 2.65;
+/// Upper surface of placed stones (world space z grows away from the camera)
+const STONE_TOP: f32 = 0.0;
+/// Lower surface of placed stones, where the board is
+const BOARD_SURFACE: f32 = 0.25;
+/// Live particles beyond this count are not spawned
+const MAX_PARTICLES: usize = 4096;
+
+/// Parameters of a burst. Pairs are [min, max] ranges picked uniformly.
+#[derive(Deserialize)]
+pub struct ParticlePreset {
+    pub count: u32,
+    pub lifetime_ms: [f32; 2],
+    /// Initial speed in world units per second
+    pub speed: [f32; 2],
+    /// Maximum angle of the initial direction from the board normal
+    pub spread_deg: f32,
+    /// Half extent of the quad in world units
+    pub size: [f32; 2],
+    /// Downward acceleration in world units per second squared
+    pub gravity: f32,
+    pub color_start: [f32; 4],
+    pub color_end: [f32; 4],
+}
+
+pub fn load_presets(
+    path: &str,
+) -> Result<HashMap<String, ParticlePreset>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        format!("Failed to load particle presets {}: {}", path, e)
+    })?;
+    serde_json::from_str(&text).map_err(|e| {
+        format!("Failed to parse particle presets {}: {}", path, e)
+    })
+}
+
+/// xorshift32: good enough for visual noise
+struct Random(u32);
+impl Random {
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1u32 << 24) as f32
+    }
+    fn range(&mut self, [min, max]: [f32; 2]) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+struct Particle {
+    position: [f32; 3],
+    velocity: [f32; 3],
+    gravity: f32,
+    age_ms: f32,
+    lifetime_ms: f32,
+    size: f32,
+    color_start: [f32; 4],
+    color_end: [f32; 4],
+}
+
+/// Per-instance attributes (location 1: position + size, location 2: color)
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ParticleInstance {
+    position_size: [f32; 4],
+    color: [f32; 4],
+}
+
+pub struct ParticleSystem {
+    presets: HashMap<String, ParticlePreset>,
+    particles: Vec<Particle>,
+    random: Random,
+    board_size: usize,
+    // referenced only through the vertex array
+    _corner_vb: Buffer<ArrayBufferTarget>,
+    instance_vb: Buffer<ArrayBufferTarget>,
+    va: VertexArray,
+    instance_count: usize,
+}
+impl ParticleSystem {
+    pub fn new(
+        presets: HashMap<String, ParticlePreset>,
+        board_size: usize,
+    ) -> Self {
+        const CORNER_VERTICES: &'static [[f32; 2]; 4] =
+            &[[-1.0, -1.0], [1.0, -1.0], [-1.0, 1.0], [1.0, 1.0]];
+        const INSTANCE_STRIDE: usize = std::mem::size_of::<ParticleInstance>();
+
+        let corner_vb = Buffer::new();
+        let instance_vb = Buffer::new();
+        let va = VertexArray::new();
+        ARRAY_BUFFER
+            .bind(&corner_vb)
+            .data(CORNER_VERTICES, gl::STATIC_DRAW);
+        va.bind();
+        unsafe {
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(
+                0,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                0,
+                std::ptr::null(),
+            );
+        }
+        ARRAY_BUFFER.bind(&instance_vb);
+        unsafe {
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(
+                1,
+                4,
+                gl::FLOAT,
+                gl::FALSE,
+                INSTANCE_STRIDE as _,
+                std::ptr::null(),
+            );
+            gl::VertexAttribDivisor(1, 1);
+            gl::EnableVertexAttribArray(2);
+            gl::VertexAttribPointer(
+                2,
+                4,
+                gl::FLOAT,
+                gl::FALSE,
+                INSTANCE_STRIDE as _,
+                (std::mem::size_of::<[f32; 4]>()) as _,
+            );
+            gl::VertexAttribDivisor(2, 1);
+        }
+        VertexArray::unbind();
+        ARRAY_BUFFER.unbind();
+
+        corner_vb.set_label("Particle Corners");
+        instance_vb.set_label("Particle Instances");
+        va.set_label("Particles");
+
+        ParticleSystem {
+            presets,
+            particles: Vec::new(),
+            random: Random(0x9e37_79b9),
+            board_size,
+            _corner_vb: corner_vb,
+            instance_vb,
+            va,
+            instance_count: 0,
+        }
+    }
+
+    pub fn set_presets(&mut self, presets: HashMap<String, ParticlePreset>) {
+        self.presets = presets;
+    }
+
+    /// Spawns a burst from the top of the stone at the cell (x, y)
+    pub fn emit(&mut self, x: f64, y: f64, preset_name: &str) {
+        let preset = match self.presets.get(preset_name) {
+            Some(p) => p,
+            None => {
+                log::warn!("Unknown particle preset: {}", preset_name);
+                return;
+            }
+        };
+        let half = self.board_size as f32 * 0.5;
+        let space = CELL_SPACE_8X8 * 8.0 / self.board_size as f32;
+        let cx = (x as f32 - half + 0.5) * space;
+        let cy = -(y as f32 - half + 0.5) * space;
+
+        let count = (preset.count as usize)
+            .min(MAX_PARTICLES.saturating_sub(self.particles.len()));
+        for _ in 0..count {
+            let r = &mut self.random;
+            let azimuth = r.next_f32() * std::f32::consts::TAU;
+            let polar = r.next_f32() * preset.spread_deg.to_radians();
+            let speed = r.range(preset.speed);
+            let (sa, ca) = azimuth.sin_cos();
+            let (sp, cp) = polar.sin_cos();
+            self.particles.push(Particle {
+                position: [cx, cy, STONE_TOP],
+                velocity: [ca * sp * speed, sa * sp * speed, -cp * speed],
+                gravity: preset.gravity,
+                age_ms: 0.0,
+                lifetime_ms: r.range(preset.lifetime_ms),
+                size: r.range(preset.size),
+                color_start: preset.color_start,
+                color_end: preset.color_end,
+            });
+        }
+    }
+
+    /// Advances the simulation and uploads the live particles
+    pub fn update(&mut self, dt_ms: f32) {
+        let dt = dt_ms / 1000.0;
+        self.particles.retain(|p| p.age_ms + dt_ms < p.lifetime_ms);
+        for p in &mut self.particles {
+            p.age_ms += dt_ms;
+            p.velocity[2] += p.gravity * dt;
+            for (x, v) in p.position.iter_mut().zip(&p.velocity) {
+                *x += v * dt;
+            }
+            // bounce on the board surface
+            if p.position[2] > BOARD_SURFACE {
+                p.position[2] = BOARD_SURFACE;
+                p.velocity[2] *= -0.4;
+            }
+        }
+
+        let instances: Vec<_> = self
+            .particles
+            .iter()
+            .map(|p| {
+                let t = p.age_ms / p.lifetime_ms;
+                let mut color = [0.0; 4];
+                for (n, c) in color.iter_mut().enumerate() {
+                    *c = p.color_start[n]
+                        + (p.color_end[n] - p.color_start[n]) * t;
+                }
+                ParticleInstance {
+                    position_size: [
+                        p.position[0],
+                        p.position[1],
+                        p.position[2],
+                        p.size,
+                    ],
+                    color,
+                }
+            })
+            .collect();
+        ARRAY_BUFFER
+            .bind(&self.instance_vb)
+            .data(&instances, gl::STREAM_DRAW)
+            .unbind();
+        self.instance_count = instances.len();
+    }
+
+    /// Draws with the particle program in use
+    pub fn draw(&self) {
+        if self.instance_count == 0 {
+            return;
+        }
+
+        self.va.bind();
+        unsafe {
+            gl::DrawArraysInstanced(
+                gl::TRIANGLE_STRIP,
+                0,
+                4,
+                self.instance_count as _,
+            );
+        }
+        VertexArray::unbind();
+    }
+}
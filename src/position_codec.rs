@@ -0,0 +1,283 @@
+//! Text forms of 8x8 positions
+//!
+//! - grid: rows of `B` black, `W` white and `_` empty as printed by the
+//!   script's `BoardState.dump()`. The side to move is implied by the discs.
+//! - OBF: 64 cells of `X` black, `O` white and `-` empty, then the side to
+//!   move (`X` or `O`), as used by solvers and test suites
+//! - base64: 23 URL-safe characters encoding both bitboards and the side to
+//!   move, short enough to paste anywhere
+//!
+//! Grid and OBF cells are listed from a1 to h8 row by row in the standard
+//! orientation, which is this board flipped vertically (see
+//! `bitboard::standard_square`), so that positions can be exchanged with
+//! other programs.
+
+use crate::bitboard::{standard_square, Color, Position, SIZE};
+
+const CELLS: usize = SIZE * SIZE;
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+/// Black and white bitboards and the side to move
+const PACKED_BYTES: usize = 17;
+const BASE64_LEN: usize = (PACKED_BYTES * 8 + 5) / 6;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PositionFormat {
+    Grid,
+    Obf,
+    Base64,
+}
+impl PositionFormat {
+    /// Guesses the format of `text`, falling back to the grid
+    pub fn detect(text: &str) -> Self {
+        let text = text.trim();
+        if text.len() == BASE64_LEN
+            && text.bytes().all(|b| BASE64_ALPHABET.contains(&b))
+        {
+            return PositionFormat::Base64;
+        }
+        let mut tokens = text.split_whitespace();
+        let is_obf = tokens.next().map_or(false, |cells| {
+            cells.len() == CELLS && cells.chars().all(|c| "XO-".contains(c))
+        });
+        if is_obf {
+            PositionFormat::Obf
+        } else {
+            PositionFormat::Grid
+        }
+    }
+}
+
+/// Parses any of the forms, as detected by `PositionFormat::detect`
+pub fn parse_position(text: &str) -> Result<(Position, Color), String> {
+    match PositionFormat::detect(text) {
+        PositionFormat::Grid => parse_grid(text),
+        PositionFormat::Obf => parse_obf(text),
+        PositionFormat::Base64 => parse_base64(text),
+    }
+}
+
+pub fn format_position(
+    pos: &Position,
+    to_move: Color,
+    format: PositionFormat,
+) -> String {
+    match format {
+        PositionFormat::Grid => format_grid(pos, to_move),
+        PositionFormat::Obf => format_obf(pos, to_move),
+        PositionFormat::Base64 => format_base64(pos, to_move),
+    }
+}
+
+/// Reads 64 cells in the standard order into black and white bitboards
+fn parse_cells(
+    cells: impl Iterator<Item = char>,
+    black: char,
+    white: char,
+    empty: char,
+) -> Result<(u64, u64), String> {
+    let (mut b, mut w, mut n) = (0, 0, 0);
+    for c in cells {
+        if n >= CELLS {
+            return Err("too many cells".into());
+        }
+        let bit = 1 << standard_square(n as u32);
+        match c {
+            _ if c == black => b |= bit,
+            _ if c == white => w |= bit,
+            _ if c == empty => (),
+            _ => return Err(format!("invalid cell: {}", c)),
+        }
+        n += 1;
+    }
+    if n < CELLS {
+        return Err(format!("{} cells given, {} required", n, CELLS));
+    }
+    Ok((b, w))
+}
+
+fn format_cells(
+    pos: &Position,
+    to_move: Color,
+    [black, white, empty]: [char; 3],
+) -> impl Iterator<Item = char> {
+    let (b, w) = pos.discs(to_move);
+    (0..CELLS as u32).map(move |n| {
+        let bit = 1 << standard_square(n);
+        if b & bit != 0 {
+            black
+        } else if w & bit != 0 {
+            white
+        } else {
+            empty
+        }
+    })
+}
+
+/// Lines with other characters than cells and whitespace are skipped, such
+/// as the score line of `BoardState.dump()`
+pub fn parse_grid(text: &str) -> Result<(Position, Color), String> {
+    let cells = text
+        .lines()
+        .filter(|l| l.chars().all(|c| "BW_".contains(c) || c.is_whitespace()))
+        .flat_map(|l| l.chars().filter(|c| !c.is_whitespace()));
+    let (black, white) = parse_cells(cells, 'B', 'W', '_')?;
+    Ok(Position::from_discs(black, white, Color::Black).implied_turn())
+}
+
+/// One row per line
+pub fn format_grid(pos: &Position, to_move: Color) -> String {
+    let cells: Vec<char> =
+        format_cells(pos, to_move, ['B', 'W', '_']).collect();
+    cells
+        .chunks(SIZE)
+        .map(|row| row.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Anything after the side to move, such as `;` and solver annotations, is
+/// ignored
+pub fn parse_obf(text: &str) -> Result<(Position, Color), String> {
+    let mut tokens = text.split_whitespace();
+    let cells = tokens.next().ok_or("empty position")?;
+    let (black, white) = parse_cells(cells.chars(), 'X', 'O', '-')?;
+    let to_move = match tokens.next().and_then(|s| s.chars().next()) {
+        Some('X') => Color::Black,
+        Some('O') => Color::White,
+        _ => return Err("side to move must be X or O".into()),
+    };
+    Ok((Position::from_discs(black, white, to_move), to_move))
+}
+
+pub fn format_obf(pos: &Position, to_move: Color) -> String {
+    let cells: String = format_cells(pos, to_move, ['X', 'O', '-']).collect();
+    let side = match to_move {
+        Color::Black => 'X',
+        Color::White => 'O',
+    };
+    format!("{} {};", cells, side)
+}
+
+pub fn parse_base64(text: &str) -> Result<(Position, Color), String> {
+    let text = text.trim();
+    if text.len() != BASE64_LEN {
+        return Err(format!("{} characters required", BASE64_LEN));
+    }
+    let mut bytes = [0u8; PACKED_BYTES];
+    let mut bits = 0u32;
+    let mut pending = 0;
+    let mut n = 0;
+    for c in text.bytes() {
+        let v = BASE64_ALPHABET
+            .iter()
+            .position(|&a| a == c)
+            .ok_or_else(|| format!("invalid character: {}", c as char))?;
+        bits = bits << 6 | v as u32;
+        pending += 6;
+        if pending >= 8 {
+            pending -= 8;
+            bytes[n] = (bits >> pending) as u8;
+            n += 1;
+        }
+    }
+
+    let u64_at = |i: usize| {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(&bytes[i..i + 8]);
+        u64::from_le_bytes(b)
+    };
+    let (black, white) = (u64_at(0), u64_at(8));
+    if black & white != 0 {
+        return Err("cells with both colors".into());
+    }
+    let to_move = match bytes[16] {
+        0 => Color::Black,
+        1 => Color::White,
+        _ => return Err("invalid side to move".into()),
+    };
+    Ok((Position::from_discs(black, white, to_move), to_move))
+}
+
+pub fn format_base64(pos: &Position, to_move: Color) -> String {
+    let (black, white) = pos.discs(to_move);
+    let mut bytes = Vec::with_capacity(PACKED_BYTES);
+    bytes.extend_from_slice(&black.to_le_bytes());
+    bytes.extend_from_slice(&white.to_le_bytes());
+    bytes.push(match to_move {
+        Color::Black => 0,
+        Color::White => 1,
+    });
+
+    let mut text = String::with_capacity(BASE64_LEN);
+    let mut bits = 0u32;
+    let mut pending = 0;
+    for b in bytes {
+        bits = bits << 8 | b as u32;
+        pending += 8;
+        while pending >= 6 {
+            pending -= 6;
+            text.push(BASE64_ALPHABET[(bits >> pending) as usize & 63] as char);
+        }
+    }
+    if pending > 0 {
+        text.push(
+            BASE64_ALPHABET[(bits << (6 - pending)) as usize & 63] as char,
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        // initial and after f5
+        let positions = [
+            (Position::initial(), Color::Black),
+            (Position::initial().play(standard_square(37)), Color::White),
+        ];
+        for &(pos, to_move) in positions.iter() {
+            for &f in &[
+                PositionFormat::Grid,
+                PositionFormat::Obf,
+                PositionFormat::Base64,
+            ] {
+                let text = format_position(&pos, to_move, f);
+                assert_eq!(PositionFormat::detect(&text), f);
+                assert_eq!(parse_position(&text).unwrap(), (pos, to_move));
+            }
+        }
+    }
+
+    #[test]
+    fn obf_in_the_standard_orientation() {
+        // after f5: black on d5, e5, f5 and e4, white on d4
+        let text = "---------------------------OX------XXX-------------------\
+                    ------- O";
+        let (pos, to_move) = parse_obf(text).unwrap();
+        assert_eq!(to_move, Color::White);
+        assert_eq!(pos, Position::initial().play(standard_square(37)));
+        assert_eq!(format_obf(&pos, to_move), text.to_owned() + ";");
+    }
+
+    #[test]
+    fn grid_with_a_score_line() {
+        let text = "white 2 black 2\n________\n________\n________\n\
+                    ___WB___\n___BW___\n________\n________\n________";
+        assert_eq!(
+            parse_grid(text).unwrap(),
+            (Position::initial(), Color::Black)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_positions() {
+        assert!(parse_obf("X O").is_err());
+        assert!(parse_grid("BW").is_err());
+        assert!(parse_base64("AAAAAAAAAAAAAAAAAAAAAAA").is_ok());
+        assert!(parse_base64("______________________A").is_err());
+    }
+}
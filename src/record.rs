@@ -0,0 +1,426 @@
+//! Game records as move list transcripts (`f5d6c3d3...`) and GGF
+//!
+//! Both formats assume the standard orientation with black starting on d5
+//! and e4, which is this board flipped vertically. Cells are converted on the
+//! way in and out so that records can be exchanged with other programs.
+//! Transcripts always start from the initial position, GGF records may start
+//! from any.
+
+use crate::bitboard::{
+    parse_square_name, square_name, square_xy, squares, standard_square, Color,
+    Position,
+};
+use crate::history::HistoryEntry;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecordFormat {
+    Transcript,
+    Ggf,
+}
+impl RecordFormat {
+    /// GGF for `.ggf` files, transcripts otherwise
+    pub fn from_path(path: &str) -> Self {
+        if path.to_ascii_lowercase().ends_with(".ggf") {
+            RecordFormat::Ggf
+        } else {
+            RecordFormat::Transcript
+        }
+    }
+}
+
+pub struct GameRecord {
+    /// Seen from the side to move
+    pub start: Position,
+    pub start_color: Color,
+    /// Moves from `start`, None for passes
+    pub moves: Vec<Option<u32>>,
+}
+impl GameRecord {
+    /// Checks moves by replaying them. Passes are inserted where the side to
+    /// move has none, so records may leave them implicit.
+    fn from_moves(
+        start: Position,
+        start_color: Color,
+        moves: impl IntoIterator<Item = Option<u32>>,
+    ) -> Result<Self, String> {
+        let mut record = GameRecord {
+            start,
+            start_color,
+            moves: Vec::new(),
+        };
+        let mut pos = start;
+        for (ply, m) in moves.into_iter().enumerate() {
+            match m {
+                Some(sq) => {
+                    if pos.legal_moves() == 0 && pos.pass().legal_moves() != 0 {
+                        record.moves.push(None);
+                        pos = pos.pass();
+                    }
+                    if pos.legal_moves() & 1 << sq == 0 {
+                        return Err(format!(
+                            "illegal move {} at ply {}",
+                            square_name(standard_square(sq)),
+                            ply + 1
+                        ));
+                    }
+                    pos = pos.play(sq);
+                }
+                None => {
+                    if pos.legal_moves() != 0 {
+                        return Err(format!(
+                            "pass with legal moves at ply {}",
+                            ply + 1
+                        ));
+                    }
+                    pos = pos.pass();
+                }
+            }
+            record.moves.push(m);
+        }
+        Ok(record)
+    }
+
+    /// Record of moves played on an 8x8 board
+    pub fn from_history(
+        start: Position,
+        start_color: Color,
+        entries: &[HistoryEntry],
+    ) -> Self {
+        GameRecord {
+            start,
+            start_color,
+            moves: entries
+                .iter()
+                .map(|e| e.cell.map(|(x, y)| x + y * 8))
+                .collect(),
+        }
+    }
+
+    /// Positions before each move with the side to move, then the final one
+    fn positions(&self) -> Vec<(Position, Color)> {
+        let mut list = vec![(self.start, self.start_color)];
+        for m in &self.moves {
+            let &(pos, color) = list.last().expect("no positions");
+            let next = match m {
+                Some(sq) => pos.play(*sq),
+                None => pos.pass(),
+            };
+            list.push((next, color.opponent()));
+        }
+        list
+    }
+
+    /// Final disc differential from black, None if the game is not over
+    fn result(&self) -> Option<i32> {
+        let &(pos, color) = self.positions().last().expect("no positions");
+        if pos.legal_moves() != 0 || pos.pass().legal_moves() != 0 {
+            return None;
+        }
+        Some(match color {
+            Color::Black => pos.final_score(),
+            Color::White => -pos.final_score(),
+        })
+    }
+
+    /// History entries replaying the record, stamped with `time_ms`
+    pub fn history_entries(&self, time_ms: f64) -> Vec<HistoryEntry> {
+        self.positions()
+            .iter()
+            .zip(&self.moves)
+            .map(|(&(pos, color), m)| HistoryEntry {
+                color,
+                cell: m.map(square_xy),
+                flipped: m.map_or_else(Vec::new, |sq| {
+                    squares(pos.flips(sq)).map(square_xy).collect()
+                }),
+                time_ms,
+            })
+            .collect()
+    }
+
+    /// Concatenated cell names, passes omitted
+    pub fn parse_transcript(text: &str) -> Result<Self, String> {
+        let chars: Vec<char> =
+            text.chars().filter(|c| !c.is_whitespace()).collect();
+        if chars.len() % 2 != 0 {
+            return Err("odd number of characters in transcript".into());
+        }
+        let moves = chars
+            .chunks(2)
+            .map(|c| {
+                let name: String = c.iter().collect();
+                parse_square_name(&name)
+                    .map(|sq| Some(standard_square(sq)))
+                    .ok_or_else(|| format!("invalid cell name: {}", name))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::from_moves(Position::initial(), Color::Black, moves)
+    }
+
+    pub fn to_transcript(&self) -> Result<String, String> {
+        if (self.start, self.start_color) != (Position::initial(), Color::Black)
+        {
+            return Err(
+                "transcripts can only start from the initial position".into()
+            );
+        }
+        Ok(self
+            .moves
+            .iter()
+            .filter_map(|m| m.map(|sq| square_name(standard_square(sq))))
+            .collect())
+    }
+
+    /// First game of a GGF file. Only 8x8 boards are supported.
+    pub fn parse_ggf(text: &str) -> Result<Self, String> {
+        let game = text.find("(;").ok_or("no game found")?;
+        let mut rest = &text[game + 2..];
+        let mut start = (Position::initial(), Color::Black);
+        let mut moves = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if rest.starts_with(";)") {
+                break;
+            }
+            let key_len = rest
+                .find(|c: char| !c.is_ascii_uppercase())
+                .ok_or("unterminated game")?;
+            let key = &rest[..key_len];
+            if key.is_empty() || !rest[key_len..].starts_with('[') {
+                return Err(format!("invalid property at: {:.16}", rest));
+            }
+            let value_end =
+                rest[key_len..].find(']').ok_or("unterminated property")?;
+            let value = &rest[key_len + 1..key_len + value_end];
+            rest = &rest[key_len + value_end + 1..];
+
+            match key {
+                "GM" if !value.eq_ignore_ascii_case("othello") => {
+                    return Err(format!("unsupported game: {}", value));
+                }
+                "TY" if value.trim() != "8" => {
+                    return Err(format!("unsupported board type: {}", value));
+                }
+                "BO" => start = parse_ggf_board(value)?,
+                "B" | "W" => {
+                    // `f5`, `f5//1.20` with an evaluation and time, or `PA`
+                    let cell = value.split('/').next().unwrap_or("").trim();
+                    if cell.eq_ignore_ascii_case("pa")
+                        || cell.eq_ignore_ascii_case("pass")
+                    {
+                        moves.push(None);
+                    } else {
+                        let sq = parse_square_name(cell)
+                            .ok_or_else(|| format!("invalid move: {}", cell))?;
+                        moves.push(Some(standard_square(sq)));
+                    }
+                }
+                _ => (),
+            }
+        }
+        Self::from_moves(start.0, start.1, moves)
+    }
+
+    pub fn to_ggf(&self, black_name: &str, white_name: &str) -> String {
+        let mut text = format!(
+            "(;GM[Othello]PC[simple_reversi]PB[{}]PW[{}]",
+            black_name, white_name
+        );
+        if let Some(score) = self.result() {
+            text += &format!("RE[{:+}.000]", score);
+        }
+        text += &format!(
+            "TY[8]BO[{}]",
+            format_ggf_board(&self.start, self.start_color)
+        );
+        for (&(_, color), m) in self.positions().iter().zip(&self.moves) {
+            let cell = m
+                .map_or("PA".to_owned(), |sq| square_name(standard_square(sq)));
+            let key = match color {
+                Color::Black => "B",
+                Color::White => "W",
+            };
+            text += &format!("{}[{}]", key, cell);
+        }
+        text + ";)\n"
+    }
+
+    /// Reads a transcript or GGF file, depending on the extension
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to load game {}: {}", path, e))?;
+        match RecordFormat::from_path(path) {
+            RecordFormat::Transcript => Self::parse_transcript(&text),
+            RecordFormat::Ggf => Self::parse_ggf(&text),
+        }
+        .map_err(|e| format!("Failed to parse game {}: {}", path, e))
+    }
+
+    /// Writes a transcript or GGF file, depending on the extension.
+    /// Missing directories are created.
+    pub fn save(
+        &self,
+        path: &str,
+        black_name: &str,
+        white_name: &str,
+    ) -> Result<(), String> {
+        let text = match RecordFormat::from_path(path) {
+            RecordFormat::Transcript => self.to_transcript()? + "\n",
+            RecordFormat::Ggf => self.to_ggf(black_name, white_name),
+        };
+        if let Some(dir) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to save game {}: {}", path, e))?;
+        }
+        std::fs::write(path, text)
+            .map_err(|e| format!("Failed to save game {}: {}", path, e))
+    }
+}
+
+/// `8`, then 64 cells of `*` black, `O` white and `-` empty in the standard
+/// orientation, then the side to move. Cells may be grouped by whitespace.
+fn parse_ggf_board(value: &str) -> Result<(Position, Color), String> {
+    let mut tokens = value.split_whitespace();
+    if tokens.next() != Some("8") {
+        return Err(format!("unsupported board: {}", value));
+    }
+    let chars: Vec<char> = tokens.flat_map(|t| t.chars()).collect();
+    if chars.len() != 65 {
+        return Err(format!("invalid board: {}", value));
+    }
+    let (mut black, mut white) = (0u64, 0u64);
+    for (n, c) in chars[..64].iter().enumerate() {
+        let bit = 1 << standard_square(n as u32);
+        match c {
+            '*' => black |= bit,
+            'O' => white |= bit,
+            '-' => (),
+            _ => return Err(format!("invalid cell in board: {}", c)),
+        }
+    }
+    let to_move = match chars[64] {
+        '*' => Color::Black,
+        'O' => Color::White,
+        c => return Err(format!("invalid side to move: {}", c)),
+    };
+    Ok((Position::from_discs(black, white, to_move), to_move))
+}
+
+fn format_ggf_board(pos: &Position, to_move: Color) -> String {
+    let (black, white) = pos.discs(to_move);
+    let mut text = "8".to_owned();
+    for n in 0..64 {
+        if n % 8 == 0 {
+            text.push(' ');
+        }
+        let bit = 1 << standard_square(n);
+        text.push(if black & bit != 0 {
+            '*'
+        } else if white & bit != 0 {
+            'O'
+        } else {
+            '-'
+        });
+    }
+    text.push(' ');
+    text.push(match to_move {
+        Color::Black => '*',
+        Color::White => 'O',
+    });
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c1() -> u32 {
+        standard_square(parse_square_name("c1").unwrap())
+    }
+
+    #[test]
+    fn standard_opening() {
+        let record = GameRecord::parse_transcript("f5d6c3").unwrap();
+        let &(pos, to_move) = record.positions().last().unwrap();
+        assert_eq!(to_move, Color::White);
+        let (black, white) = pos.discs(to_move);
+        let names = |b: u64| {
+            let mut n: Vec<String> = squares(b)
+                .map(|sq| square_name(standard_square(sq)))
+                .collect();
+            n.sort();
+            n
+        };
+        assert_eq!(names(black), ["c3", "d4", "e4", "e5", "f5"]);
+        assert_eq!(names(white), ["d5", "d6"]);
+        assert_eq!(record.to_transcript().unwrap(), "f5d6c3");
+    }
+
+    #[test]
+    fn rejects_illegal_moves() {
+        let e = GameRecord::parse_transcript("f5f5").err().unwrap();
+        assert_eq!(e, "illegal move f5 at ply 2");
+        assert!(GameRecord::parse_transcript("a1").is_err());
+        assert!(GameRecord::parse_transcript("f5d").is_err());
+    }
+
+    #[test]
+    fn ggf_round_trip() {
+        let record = GameRecord::parse_transcript("f5d6c3d3c4f4f6").unwrap();
+        let text = record.to_ggf("black", "white");
+        assert!(text.contains("B[f5]W[d6]B[c3]"));
+        let parsed = GameRecord::parse_ggf(&text).unwrap();
+        assert_eq!(parsed.moves, record.moves);
+        assert_eq!(parsed.to_transcript().unwrap(), "f5d6c3d3c4f4f6");
+    }
+
+    #[test]
+    fn ggf_from_another_start() {
+        // after f5
+        let start = Position::initial().play(standard_square(37));
+        let record = GameRecord {
+            start,
+            start_color: Color::White,
+            moves: vec![Some(start.legal_moves().trailing_zeros())],
+        };
+        assert!(record.to_transcript().is_err());
+        let parsed =
+            GameRecord::parse_ggf(&record.to_ggf("black", "white")).unwrap();
+        assert_eq!(parsed.start, start);
+        assert_eq!(parsed.start_color, Color::White);
+        assert_eq!(parsed.moves, record.moves);
+    }
+
+    #[test]
+    fn ggf_with_evaluations_and_passes() {
+        let text = "(;GM[Othello]TY[8]BO[8 -------- -------- -------- \
+                    ---O*--- ---*O--- -------- -------- -------- *]\
+                    B[f5//0.01]W[d6/-1.20/2.5];)";
+        let record = GameRecord::parse_ggf(text).unwrap();
+        assert_eq!(record.to_transcript().unwrap(), "f5d6");
+
+        // black has no move beside the white corner, white takes c1
+        let text = "(;GM[Othello]TY[8]BO[8 O*------ -------- -------- \
+                    -------- -------- -------- -------- -------- *]\
+                    B[PA//0.00]W[c1/-64.00/1.0];)";
+        let record = GameRecord::parse_ggf(text).unwrap();
+        assert_eq!(record.moves, [None, Some(c1())]);
+        assert!(record.to_ggf("black", "white").contains("B[PA]W[c1]"));
+    }
+
+    #[test]
+    fn implicit_passes_are_inserted() {
+        let text = "(;GM[Othello]TY[8]BO[8 O*------ -------- -------- \
+                    -------- -------- -------- -------- -------- *]\
+                    W[c1];)";
+        let record = GameRecord::parse_ggf(text).unwrap();
+        assert_eq!(record.moves, [None, Some(c1())]);
+        let entries = record.history_entries(0.0);
+        assert_eq!(entries[0].color, Color::Black);
+        assert_eq!(entries[0].cell, None);
+        assert_eq!(entries[1].color, Color::White);
+
+        let e = GameRecord::parse_ggf("(;GM[Othello]B[PA];)").err().unwrap();
+        assert_eq!(e, "pass with legal moves at ply 1");
+    }
+}
@@ -0,0 +1,139 @@
+use std::io::Write;
+
+use crate::glres::{Framebuffer, GlObject, Renderbuffer};
+
+/// Where the frame is rendered before being presented
+pub enum RenderTarget {
+    /// Window's default framebuffer (may be multisampled by the window hint)
+    Default {
+        multisample: bool,
+        width: i32,
+        height: i32,
+    },
+    /// Offscreen multisampled framebuffer resolved into the default one
+    OffscreenMultisample(MultisampleFramebuffer),
+}
+impl RenderTarget {
+    pub fn begin(&self) {
+        unsafe {
+            match self {
+                RenderTarget::Default { multisample, .. } => {
+                    Framebuffer::bind_default(gl::FRAMEBUFFER);
+                    if *multisample {
+                        gl::Enable(gl::MULTISAMPLE);
+                    } else {
+                        gl::Disable(gl::MULTISAMPLE);
+                    }
+                }
+                RenderTarget::OffscreenMultisample(fb) => {
+                    fb.fb.bind(gl::FRAMEBUFFER);
+                    gl::Enable(gl::MULTISAMPLE);
+                }
+            }
+        }
+    }
+
+    /// Resolves rendered contents into the default framebuffer
+    pub fn resolve(&self) {
+        if let RenderTarget::OffscreenMultisample(fb) = self {
+            fb.resolve();
+        }
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        match self {
+            &RenderTarget::Default { width, height, .. } => (width, height),
+            RenderTarget::OffscreenMultisample(fb) => (fb.width, fb.height),
+        }
+    }
+
+    /// Writes resolved contents of the default framebuffer as binary PPM
+    pub fn capture_screenshot(&self, path: &str) -> std::io::Result<()> {
+        let (width, height) = self.size();
+        let mut pixels = vec![0u8; (width * height * 3) as usize];
+        Framebuffer::bind_default(gl::READ_FRAMEBUFFER);
+        unsafe {
+            gl::ReadBuffer(gl::BACK);
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                0,
+                0,
+                width,
+                height,
+                gl::RGB,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as _,
+            );
+        }
+
+        let mut fp = std::io::BufWriter::new(std::fs::File::create(path)?);
+        write!(fp, "P6\n{} {}\n255\n", width, height)?;
+        // GL origin is bottom-left
+        for row in pixels.chunks((width * 3) as usize).rev() {
+            fp.write_all(row)?;
+        }
+        fp.flush()
+    }
+}
+
+pub struct MultisampleFramebuffer {
+    fb: Framebuffer,
+    _color_rb: Renderbuffer,
+    _depth_rb: Renderbuffer,
+    width: i32,
+    height: i32,
+}
+impl MultisampleFramebuffer {
+    pub fn new(width: i32, height: i32, samples: u32) -> Self {
+        let color_rb =
+            Renderbuffer::new_multisample(gl::RGBA8, width, height, samples);
+        color_rb.set_label("Multisample Color");
+        let depth_rb = Renderbuffer::new_multisample(
+            gl::DEPTH_COMPONENT24,
+            width,
+            height,
+            samples,
+        );
+        depth_rb.set_label("Multisample Depth");
+
+        let fb = Framebuffer::new();
+        let status = fb
+            .bind(gl::FRAMEBUFFER)
+            .attach_renderbuffer(gl::COLOR_ATTACHMENT0, &color_rb)
+            .attach_renderbuffer(gl::DEPTH_ATTACHMENT, &depth_rb)
+            .status();
+        fb.set_label("Multisample Framebuffer");
+        Framebuffer::bind_default(gl::FRAMEBUFFER);
+        if let Err(status) = status {
+            panic!("Multisample framebuffer incomplete: 0x{:04x}", status);
+        }
+
+        MultisampleFramebuffer {
+            fb,
+            _color_rb: color_rb,
+            _depth_rb: depth_rb,
+            width,
+            height,
+        }
+    }
+
+    fn resolve(&self) {
+        self.fb.bind(gl::READ_FRAMEBUFFER);
+        Framebuffer::bind_default(gl::DRAW_FRAMEBUFFER);
+        unsafe {
+            gl::BlitFramebuffer(
+                0,
+                0,
+                self.width,
+                self.height,
+                0,
+                0,
+                self.width,
+                self.height,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+        }
+        Framebuffer::bind_default(gl::FRAMEBUFFER);
+    }
+}
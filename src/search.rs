@@ -0,0 +1,275 @@
+//! Alpha-beta engine running on a worker thread
+//!
+//! Negamax with iterative deepening under a time budget. Moves are ordered
+//! by the best move of the previous iteration, then by square weights.
+//! Positions near the end of the game are solved exactly instead.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::bitboard::{squares, Position};
+use crate::book::Book;
+use crate::endgame;
+
+/// Scores of finished games are offset by this to rank above evaluations
+pub const WIN_SCORE: i32 = 100_000;
+/// Time is checked every this many nodes
+const NODES_PER_TIME_CHECK: u64 = 1024;
+/// Positions with this many empties or less are played perfectly
+const ENDGAME_EMPTIES: u32 = 14;
+
+/// Static value of owning each cell
+#[rustfmt::skip]
+const SQUARE_WEIGHTS: [i32; 64] = [
+    100, -20,  10,   5,   5,  10, -20, 100,
+    -20, -50,  -2,  -2,  -2,  -2, -50, -20,
+     10,  -2,  -1,  -1,  -1,  -1,  -2,  10,
+      5,  -2,  -1,  -1,  -1,  -1,  -2,   5,
+      5,  -2,  -1,  -1,  -1,  -1,  -2,   5,
+     10,  -2,  -1,  -1,  -1,  -1,  -2,  10,
+    -20, -50,  -2,  -2,  -2,  -2, -50, -20,
+    100, -20,  10,   5,   5,  10, -20, 100,
+];
+const MOBILITY_WEIGHT: i32 = 8;
+
+fn evaluate(pos: &Position) -> i32 {
+    let weights = |b: u64| squares(b).map(|sq| SQUARE_WEIGHTS[sq as usize]);
+    let material: i32 =
+        weights(pos.player).sum::<i32>() - weights(pos.opponent).sum::<i32>();
+    let mobility = pos.legal_moves().count_ones() as i32
+        - pos.pass().legal_moves().count_ones() as i32;
+    material + mobility * MOBILITY_WEIGHT
+}
+
+/// Search score of a final disc differential
+fn final_value(score: i32) -> i32 {
+    match score {
+        0 => 0,
+        s if s > 0 => WIN_SCORE + s,
+        s => -WIN_SCORE + s,
+    }
+}
+
+/// Moves sorted by square weight, `first` in front if legal
+fn ordered_moves(moves: u64, first: Option<u32>) -> Vec<u32> {
+    let mut list: Vec<_> = squares(moves).collect();
+    list.sort_by_key(|&sq| (Some(sq) != first, -SQUARE_WEIGHTS[sq as usize]));
+    list
+}
+
+pub struct SearchResult {
+    /// None if the side to move has to pass
+    pub best_move: Option<u32>,
+    /// From the side to move, WIN_SCORE + disc difference for won games
+    pub score: i32,
+    /// Deepest fully searched depth
+    pub depth: u32,
+    pub nodes: u64,
+}
+
+struct Searcher {
+    deadline: Instant,
+    nodes: u64,
+    aborted: bool,
+}
+impl Searcher {
+    fn negamax(
+        &mut self,
+        pos: &Position,
+        depth: u32,
+        mut alpha: i32,
+        beta: i32,
+        passed: bool,
+    ) -> i32 {
+        self.nodes += 1;
+        if self.nodes % NODES_PER_TIME_CHECK == 0
+            && Instant::now() >= self.deadline
+        {
+            self.aborted = true;
+        }
+        if self.aborted {
+            return 0;
+        }
+
+        let moves = pos.legal_moves();
+        if moves == 0 {
+            if passed {
+                return final_value(pos.final_score());
+            }
+            return -self.negamax(&pos.pass(), depth, -beta, -alpha, true);
+        }
+        if depth == 0 {
+            return evaluate(pos);
+        }
+
+        let mut best = -i32::MAX;
+        for sq in ordered_moves(moves, None) {
+            let v =
+                -self.negamax(&pos.play(sq), depth - 1, -beta, -alpha, false);
+            best = best.max(v);
+            alpha = alpha.max(v);
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+}
+
+/// Searches deeper until the budget runs out.
+/// At least one iteration is completed so that a move is always returned.
+pub fn search(pos: &Position, budget: Duration) -> SearchResult {
+    let moves = pos.legal_moves();
+    let mut result = SearchResult {
+        best_move: None,
+        score: 0,
+        depth: 0,
+        nodes: 0,
+    };
+    if moves == 0 {
+        return result;
+    }
+    let empties = pos.empties().count_ones();
+    if empties <= ENDGAME_EMPTIES {
+        let s = endgame::solve(pos);
+        result.best_move = s.best_move;
+        result.score = final_value(s.score);
+        result.depth = empties;
+        result.nodes = s.nodes;
+        return result;
+    }
+
+    let start = Instant::now();
+    let mut searcher = Searcher {
+        // the first iteration runs to completion
+        deadline: start + Duration::from_secs(3600),
+        nodes: 0,
+        aborted: false,
+    };
+    for depth in 1..=empties {
+        let mut best = None;
+        let mut alpha = -i32::MAX;
+        for sq in ordered_moves(moves, result.best_move) {
+            let v = -searcher.negamax(
+                &pos.play(sq),
+                depth - 1,
+                -i32::MAX,
+                -alpha,
+                false,
+            );
+            if searcher.aborted {
+                break;
+            }
+            if v > alpha {
+                alpha = v;
+                best = Some(sq);
+            }
+        }
+        if searcher.aborted {
+            break;
+        }
+        result.best_move = best;
+        result.score = alpha;
+        result.depth = depth;
+        searcher.deadline = start + budget;
+        // remaining time is unlikely to be enough for the next depth
+        if start.elapsed() * 2 >= budget {
+            break;
+        }
+    }
+    result.nodes = searcher.nodes;
+    result
+}
+
+pub enum SearchKind {
+    /// Best move found within the time budget, or a book move
+    BestMove(Duration),
+    /// Exact endgame solution. `score` of the result is the final disc
+    /// differential.
+    Solve,
+}
+
+pub struct SearchRequest {
+    /// Returned with the result
+    pub id: u32,
+    pub position: Position,
+    pub kind: SearchKind,
+}
+
+/// Runs searches on a background thread, one at a time in request order
+pub struct SearchWorker {
+    requests: Sender<SearchRequest>,
+    results: Receiver<(SearchRequest, SearchResult)>,
+}
+impl SearchWorker {
+    pub fn new(book: Option<Arc<Book>>) -> Self {
+        let (requests, request_rx) = channel::<SearchRequest>();
+        let (result_tx, results) = channel();
+        std::thread::Builder::new()
+            .name("search".into())
+            .spawn(move || {
+                // xorshift32 seeded by the clock, to vary book moves
+                let mut seed = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(1, |d| d.subsec_nanos())
+                    | 1;
+                let mut random = move || {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 17;
+                    seed ^= seed << 5;
+                    seed as f64 / (u32::MAX as f64 + 1.0)
+                };
+
+                // ends when the worker is dropped
+                for r in request_rx {
+                    let result = match r.kind {
+                        SearchKind::BestMove(budget) => match book
+                            .as_ref()
+                            .and_then(|b| b.pick(&r.position, random()))
+                        {
+                            Some(sq) => SearchResult {
+                                best_move: Some(sq),
+                                score: 0,
+                                depth: 0,
+                                nodes: 0,
+                            },
+                            None => search(&r.position, budget),
+                        },
+                        SearchKind::Solve => {
+                            let s = endgame::solve(&r.position);
+                            SearchResult {
+                                best_move: s.best_move,
+                                score: s.score,
+                                depth: r.position.empties().count_ones(),
+                                nodes: s.nodes,
+                            }
+                        }
+                    };
+                    log::debug!(
+                        "Searched depth {} ({} nodes), score {}",
+                        result.depth,
+                        result.nodes,
+                        result.score
+                    );
+                    if result_tx.send((r, result)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Failed to spawn search thread");
+
+        SearchWorker { requests, results }
+    }
+
+    pub fn request(&self, request: SearchRequest) {
+        self.requests
+            .send(request)
+            .expect("search thread has terminated");
+    }
+
+    /// Results finished since the last call
+    pub fn poll(&self) -> Vec<(SearchRequest, SearchResult)> {
+        self.results.try_iter().collect()
+    }
+}
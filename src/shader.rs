@@ -0,0 +1,544 @@
+use std::collections::HashMap;
+
+use crate::gl_debug;
+use crate::glres::GlObject;
+use crate::std140::Std140;
+
+pub struct Shader(gl::types::GLuint);
+impl Shader {
+    /// Reads a shader source, expanding `#include "path"` lines
+    /// (path is relative to the including file)
+    fn load_source(path: &std::path::Path) -> Result<String, String> {
+        Self::load_source_included(path, &mut Vec::new())
+    }
+
+    /// `load_source` with the chain of files including `path`, which must
+    /// not include one of them again
+    fn load_source_included(
+        path: &std::path::Path,
+        including: &mut Vec<std::path::PathBuf>,
+    ) -> Result<String, String> {
+        const MAX_INCLUDE_DEPTH: usize = 16;
+        let code = std::fs::read_to_string(path).map_err(|e| {
+            format!("Failed to load shader {}: {}", path.display(), e)
+        })?;
+        // the file was read, so it exists
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.into());
+        if including.contains(&canonical) {
+            return Err(format!("Recursive #include of {}", path.display()));
+        }
+        if including.len() >= MAX_INCLUDE_DEPTH {
+            return Err(format!(
+                "#include nested too deeply at {}",
+                path.display()
+            ));
+        }
+
+        including.push(canonical);
+        let mut expanded = String::with_capacity(code.len());
+        for line in code.lines() {
+            match line.trim().strip_prefix("#include") {
+                Some(target) => {
+                    let target = target.trim().trim_matches('"');
+                    let base =
+                        path.parent().unwrap_or(std::path::Path::new("."));
+                    expanded += &Self::load_source_included(
+                        &base.join(target),
+                        including,
+                    )?;
+                }
+                None => expanded += line,
+            }
+            expanded.push('\n');
+        }
+        including.pop();
+
+        Ok(expanded)
+    }
+
+    /// Returns the info log as an error if compilation was not successful
+    pub fn compile_file(
+        ty: gl::types::GLenum,
+        path: &str,
+    ) -> Result<Self, String> {
+        let code = Self::load_source(path.as_ref())?;
+        unsafe {
+            let sh = Shader(gl::CreateShader(ty));
+            gl::ShaderSource(
+                sh.0,
+                1,
+                &(code.as_ptr() as *const i8) as _,
+                &(code.len() as _),
+            );
+            gl::CompileShader(sh.0);
+            let mut compilation_succeeded_flag = 0;
+            gl::GetShaderiv(
+                sh.0,
+                gl::COMPILE_STATUS,
+                &mut compilation_succeeded_flag,
+            );
+            if compilation_succeeded_flag == gl::FALSE as _ {
+                let mut infolog_length = 0;
+                gl::GetShaderiv(sh.0, gl::INFO_LOG_LENGTH, &mut infolog_length);
+                let mut infolog = vec![0u8; infolog_length as usize];
+                gl::GetShaderInfoLog(
+                    sh.0,
+                    infolog_length,
+                    std::ptr::null_mut(),
+                    infolog.as_mut_ptr() as _,
+                );
+                return Err(format!(
+                    "Shader Compilation was not successful ({}): {}",
+                    path,
+                    std::ffi::CStr::from_bytes_with_nul_unchecked(&infolog)
+                        .to_string_lossy()
+                ));
+            }
+
+            Ok(sh)
+        }
+    }
+}
+impl GlObject for Shader {
+    const IDENTIFIER: gl::types::GLenum = gl::SHADER;
+
+    fn handle(&self) -> gl::types::GLuint {
+        self.0
+    }
+}
+impl Drop for Shader {
+    fn drop(&mut self) {
+        gl_debug::forget_object(Self::IDENTIFIER, self.0);
+        unsafe {
+            gl::DeleteShader(self.0);
+        }
+    }
+}
+
+/// Active uniform variable queried after linking
+#[derive(Debug, Clone)]
+pub struct ActiveUniform {
+    /// -1 for members of uniform blocks
+    pub location: gl::types::GLint,
+    pub ty: gl::types::GLenum,
+    /// -1 for uniforms in the default block
+    pub block_index: gl::types::GLint,
+    /// Byte offset in the uniform block (-1 for the default block)
+    pub offset: gl::types::GLint,
+}
+
+/// Active uniform block queried after linking
+#[derive(Debug, Clone)]
+pub struct ActiveUniformBlock {
+    pub index: gl::types::GLuint,
+    pub data_size: gl::types::GLint,
+}
+
+/// Maps GL types of active uniforms to GLSL type names
+fn glsl_type_name(ty: gl::types::GLenum) -> &'static str {
+    match ty {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::INT => "int",
+        gl::UNSIGNED_INT => "uint",
+        gl::BOOL => "bool",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::SAMPLER_2D => "sampler2D",
+        _ => "(unknown)",
+    }
+}
+
+/// Values which can be set to uniform variables in the default block
+pub trait UniformValue {
+    const GL_TYPE: gl::types::GLenum;
+
+    /// Sets the value to the location of the program currently in use
+    fn apply(&self, location: gl::types::GLint);
+}
+impl UniformValue for f32 {
+    const GL_TYPE: gl::types::GLenum = gl::FLOAT;
+
+    fn apply(&self, location: gl::types::GLint) {
+        unsafe { gl::Uniform1f(location, *self) }
+    }
+}
+impl UniformValue for [f32; 2] {
+    const GL_TYPE: gl::types::GLenum = gl::FLOAT_VEC2;
+
+    fn apply(&self, location: gl::types::GLint) {
+        unsafe { gl::Uniform2fv(location, 1, self.as_ptr()) }
+    }
+}
+impl UniformValue for [f32; 3] {
+    const GL_TYPE: gl::types::GLenum = gl::FLOAT_VEC3;
+
+    fn apply(&self, location: gl::types::GLint) {
+        unsafe { gl::Uniform3fv(location, 1, self.as_ptr()) }
+    }
+}
+impl UniformValue for [f32; 4] {
+    const GL_TYPE: gl::types::GLenum = gl::FLOAT_VEC4;
+
+    fn apply(&self, location: gl::types::GLint) {
+        unsafe { gl::Uniform4fv(location, 1, self.as_ptr()) }
+    }
+}
+impl UniformValue for i32 {
+    const GL_TYPE: gl::types::GLenum = gl::INT;
+
+    fn apply(&self, location: gl::types::GLint) {
+        unsafe { gl::Uniform1i(location, *self) }
+    }
+}
+impl UniformValue for u32 {
+    const GL_TYPE: gl::types::GLenum = gl::UNSIGNED_INT;
+
+    fn apply(&self, location: gl::types::GLint) {
+        unsafe { gl::Uniform1ui(location, *self) }
+    }
+}
+/// Texture unit index for sampler2D uniforms
+pub struct TextureUnit(pub i32);
+impl UniformValue for TextureUnit {
+    const GL_TYPE: gl::types::GLenum = gl::SAMPLER_2D;
+
+    fn apply(&self, location: gl::types::GLint) {
+        unsafe { gl::Uniform1i(location, self.0) }
+    }
+}
+/// mat4 (column-major as GL expects)
+impl UniformValue for [f32; 4 * 4] {
+    const GL_TYPE: gl::types::GLenum = gl::FLOAT_MAT4;
+
+    fn apply(&self, location: gl::types::GLint) {
+        unsafe { gl::UniformMatrix4fv(location, 1, gl::FALSE, self.as_ptr()) }
+    }
+}
+
+pub struct Program {
+    handle: gl::types::GLuint,
+    uniforms: HashMap<String, ActiveUniform>,
+    uniform_blocks: HashMap<String, ActiveUniformBlock>,
+}
+impl Program {
+    /// Returns the info log as an error if linking was not successful
+    pub fn link_shaders(shaders: &[&Shader]) -> Result<Self, String> {
+        Self::link_shaders_with_attributes(shaders, &[])
+    }
+    /// Links with explicit vertex attribute locations
+    /// (GLSL 1.50 has no `layout(location = N)` for inputs)
+    pub fn link_shaders_with_attributes(
+        shaders: &[&Shader],
+        attributes: &[(gl::types::GLuint, &str)],
+    ) -> Result<Self, String> {
+        unsafe {
+            let mut p = Program {
+                handle: gl::CreateProgram(),
+                uniforms: HashMap::new(),
+                uniform_blocks: HashMap::new(),
+            };
+            for sh in shaders {
+                gl::AttachShader(p.handle, sh.0);
+            }
+            for &(location, name) in attributes {
+                let name = std::ffi::CString::new(name)
+                    .expect("attribute name contains nul");
+                gl::BindAttribLocation(p.handle, location, name.as_ptr());
+            }
+            gl::LinkProgram(p.handle);
+            let mut link_succeeded_flag = 0;
+            gl::GetProgramiv(
+                p.handle,
+                gl::LINK_STATUS,
+                &mut link_succeeded_flag,
+            );
+            if link_succeeded_flag == gl::FALSE as _ {
+                let mut infolog_length = 0;
+                gl::GetProgramiv(
+                    p.handle,
+                    gl::INFO_LOG_LENGTH,
+                    &mut infolog_length,
+                );
+                let mut infolog = vec![0u8; infolog_length as usize];
+                gl::GetProgramInfoLog(
+                    p.handle,
+                    infolog_length,
+                    std::ptr::null_mut(),
+                    infolog.as_mut_ptr() as _,
+                );
+                return Err(format!(
+                    "Shader Linking was not successful: {}",
+                    std::ffi::CStr::from_bytes_with_nul_unchecked(&infolog)
+                        .to_string_lossy()
+                ));
+            }
+
+            p.reflect();
+            Ok(p)
+        }
+    }
+
+    /// Enumerates active uniforms and uniform blocks
+    unsafe fn reflect(&mut self) {
+        let mut uniform_count = 0;
+        let mut max_name_length = 0;
+        gl::GetProgramiv(self.handle, gl::ACTIVE_UNIFORMS, &mut uniform_count);
+        gl::GetProgramiv(
+            self.handle,
+            gl::ACTIVE_UNIFORM_MAX_LENGTH,
+            &mut max_name_length,
+        );
+        let indices: Vec<gl::types::GLuint> = (0..uniform_count as _).collect();
+        let query = |pname| {
+            let mut values = vec![0; indices.len()];
+            gl::GetActiveUniformsiv(
+                self.handle,
+                indices.len() as _,
+                indices.as_ptr(),
+                pname,
+                values.as_mut_ptr(),
+            );
+            values
+        };
+        let block_indices = query(gl::UNIFORM_BLOCK_INDEX);
+        let offsets = query(gl::UNIFORM_OFFSET);
+
+        let mut name_buf = vec![0u8; max_name_length.max(1) as usize];
+        for &index in &indices {
+            let (mut name_length, mut array_size, mut ty) = (0, 0, 0);
+            // array_size is not tracked: uniform arrays are not used yet
+            gl::GetActiveUniform(
+                self.handle,
+                index,
+                name_buf.len() as _,
+                &mut name_length,
+                &mut array_size,
+                &mut ty,
+                name_buf.as_mut_ptr() as _,
+            );
+            let name =
+                String::from_utf8_lossy(&name_buf[..name_length as usize])
+                    .into_owned();
+            let location = match std::ffi::CString::new(name.as_bytes()) {
+                Ok(n) => gl::GetUniformLocation(self.handle, n.as_ptr()),
+                Err(_) => -1,
+            };
+            // arrays in the default block are reported as "name[0]"
+            let name = match name.strip_suffix("[0]") {
+                Some(n) if block_indices[index as usize] < 0 => n.to_owned(),
+                _ => name,
+            };
+
+            self.uniforms.insert(
+                name,
+                ActiveUniform {
+                    location,
+                    ty,
+                    block_index: block_indices[index as usize],
+                    offset: offsets[index as usize],
+                },
+            );
+        }
+
+        let mut block_count = 0;
+        gl::GetProgramiv(
+            self.handle,
+            gl::ACTIVE_UNIFORM_BLOCKS,
+            &mut block_count,
+        );
+        for index in 0..block_count as gl::types::GLuint {
+            let mut name_length = 0;
+            gl::GetActiveUniformBlockiv(
+                self.handle,
+                index,
+                gl::UNIFORM_BLOCK_NAME_LENGTH,
+                &mut name_length,
+            );
+            let mut name = vec![0u8; name_length.max(1) as usize];
+            gl::GetActiveUniformBlockName(
+                self.handle,
+                index,
+                name.len() as _,
+                &mut name_length,
+                name.as_mut_ptr() as _,
+            );
+            let mut data_size = 0;
+            gl::GetActiveUniformBlockiv(
+                self.handle,
+                index,
+                gl::UNIFORM_BLOCK_DATA_SIZE,
+                &mut data_size,
+            );
+
+            self.uniform_blocks.insert(
+                String::from_utf8_lossy(&name[..name_length as usize])
+                    .into_owned(),
+                ActiveUniformBlock { index, data_size },
+            );
+        }
+    }
+
+    pub fn uniform(&self, name: &str) -> Option<&ActiveUniform> {
+        self.uniforms.get(name)
+    }
+    pub fn uniform_block(&self, name: &str) -> Option<&ActiveUniformBlock> {
+        self.uniform_blocks.get(name)
+    }
+
+    /// Checks that the uniform is active and has the type of `T`
+    pub fn require_uniform<T: UniformValue>(
+        &self,
+        name: &str,
+    ) -> Result<(), String> {
+        let u = self
+            .uniform(name)
+            .ok_or_else(|| format!("no {} uniform defined", name))?;
+        if u.ty != T::GL_TYPE {
+            return Err(format!(
+                "uniform {} is declared as {} but set as {}",
+                name,
+                glsl_type_name(u.ty),
+                glsl_type_name(T::GL_TYPE)
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the uniform block `block_name` contains an array member
+    /// `array_name` of `count` elements laid out as `T`, and binds the block
+    /// to `binding`
+    pub fn bind_uniform_block_array<T: Std140>(
+        &self,
+        block_name: &str,
+        array_name: &str,
+        count: usize,
+        binding: gl::types::GLuint,
+    ) -> Result<(), String> {
+        let block = self.uniform_block(block_name).ok_or_else(|| {
+            format!("no {} uniform block defined", block_name)
+        })?;
+        if (block.data_size as usize) < crate::std140::array_size::<T>(count) {
+            return Err(format!(
+                "uniform block {} is smaller ({} bytes) than {} {}s",
+                block_name,
+                block.data_size,
+                count,
+                T::GLSL_TYPE
+            ));
+        }
+
+        for f in T::FIELDS {
+            let member = |n| {
+                let name =
+                    format!("{}.{}[{}].{}", block_name, array_name, n, f.name);
+                self.uniform(&name)
+                    .ok_or_else(|| format!("no {} uniform defined", name))
+            };
+            let first = member(0)?;
+            if glsl_type_name(first.ty) != f.glsl_type {
+                return Err(format!(
+                    "{}.{} is declared as {} in GLSL but {} in Rust",
+                    T::GLSL_TYPE,
+                    f.name,
+                    glsl_type_name(first.ty),
+                    f.glsl_type
+                ));
+            }
+            if first.offset as usize != f.offset {
+                return Err(format!(
+                    "{}.{} is at offset {} in GLSL but {} in Rust",
+                    T::GLSL_TYPE,
+                    f.name,
+                    first.offset,
+                    f.offset
+                ));
+            }
+            // some drivers enumerate only the first element of arrays
+            if let Ok(second) = member(1) {
+                let stride = (second.offset - first.offset) as usize;
+                if stride != T::ARRAY_STRIDE {
+                    return Err(format!(
+                        "{} array stride is {} in GLSL but {} in Rust",
+                        T::GLSL_TYPE,
+                        stride,
+                        T::ARRAY_STRIDE
+                    ));
+                }
+            }
+        }
+
+        unsafe {
+            gl::UniformBlockBinding(self.handle, block.index, binding);
+        }
+        Ok(())
+    }
+
+    pub fn use_program(&self) -> &Self {
+        gl_debug::record_binding(gl::PROGRAM, self.handle);
+        unsafe {
+            gl::UseProgram(self.handle);
+        }
+        self
+    }
+
+    /// Sets a value to the uniform in the default block.
+    /// The program must be in use.
+    pub fn set_uniform<T: UniformValue>(&self, name: &str, value: T) -> &Self {
+        // inactive (optimized out) uniforms are silently ignored like GL does
+        if let Some(u) = self.uniform(name).filter(|u| u.block_index < 0) {
+            debug_assert_eq!(
+                u.ty,
+                T::GL_TYPE,
+                "uniform {} type mismatch",
+                name
+            );
+            value.apply(u.location);
+        }
+        self
+    }
+}
+impl GlObject for Program {
+    const IDENTIFIER: gl::types::GLenum = gl::PROGRAM;
+
+    fn handle(&self) -> gl::types::GLuint {
+        self.handle
+    }
+}
+impl Drop for Program {
+    fn drop(&mut self) {
+        gl_debug::forget_object(Self::IDENTIFIER, self.handle);
+        unsafe {
+            gl::DeleteProgram(self.handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn includes_are_relative_and_must_not_recurse() {
+        let dir = std::env::temp_dir().join("reversi_shader_include_test");
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        let write = |name: &str, code: &str| {
+            std::fs::write(dir.join(name), code).unwrap();
+        };
+        write("main.fsh", "#include \"lib/common.glsl\"\nvoid main() {}\n");
+        write("lib/common.glsl", "  #include \"decl.glsl\"\nfloat f;\n");
+        write("lib/decl.glsl", "int i;\n");
+        write("a.glsl", "#include \"b.glsl\"\n");
+        write("b.glsl", "#include \"a.glsl\"\n");
+
+        assert_eq!(
+            Shader::load_source(&dir.join("main.fsh")).unwrap(),
+            "int i;\n\nfloat f;\n\nvoid main() {}\n"
+        );
+        let e = Shader::load_source(&dir.join("a.glsl")).unwrap_err();
+        assert!(e.contains("Recursive #include"), "{}", e);
+        assert!(Shader::load_source(&dir.join("missing.glsl")).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+//! std140 uniform block layout
+
+pub use std140_derive::Std140;
+
+pub const fn round_up(x: usize, align: usize) -> usize {
+    (x + align - 1) / align * align
+}
+pub const fn max(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+pub struct Std140Field {
+    /// Member name in GLSL
+    pub name: &'static str,
+    pub offset: usize,
+    pub glsl_type: &'static str,
+}
+
+/// Types which can be stored in a std140 layout uniform block
+pub trait Std140: Copy {
+    /// Base alignment
+    const ALIGN: usize;
+    /// Size occupied in a block (excluding array padding)
+    const SIZE: usize;
+    /// Stride of array elements (rounded up to the alignment of vec4)
+    const ARRAY_STRIDE: usize = round_up(max(Self::SIZE, Self::ALIGN), 16);
+    const GLSL_TYPE: &'static str;
+    /// Members of structures
+    const FIELDS: &'static [Std140Field] = &[];
+
+    /// Writes self at the head of `buf`
+    fn write_std140(&self, buf: &mut [u8]);
+}
+
+macro_rules! impl_scalar {
+    ($t: ty, $glsl: expr) => {
+        impl Std140 for $t {
+            const ALIGN: usize = 4;
+            const SIZE: usize = 4;
+            const GLSL_TYPE: &'static str = $glsl;
+
+            fn write_std140(&self, buf: &mut [u8]) {
+                buf[..4].copy_from_slice(&self.to_ne_bytes());
+            }
+        }
+    };
+}
+impl_scalar!(u32, "uint");
+impl_scalar!(i32, "int");
+impl_scalar!(f32, "float");
+
+macro_rules! impl_vector {
+    ($n: expr, $align: expr, $glsl: expr) => {
+        impl Std140 for [f32; $n] {
+            const ALIGN: usize = $align;
+            const SIZE: usize = 4 * $n;
+            const GLSL_TYPE: &'static str = $glsl;
+
+            fn write_std140(&self, buf: &mut [u8]) {
+                for (v, b) in self.iter().zip(buf.chunks_exact_mut(4)) {
+                    b.copy_from_slice(&v.to_ne_bytes());
+                }
+            }
+        }
+    };
+}
+impl_vector!(2, 8, "vec2");
+impl_vector!(3, 16, "vec3");
+impl_vector!(4, 16, "vec4");
+
+/// Byte size of an array of `count` elements
+pub const fn array_size<T: Std140>(count: usize) -> usize {
+    T::ARRAY_STRIDE * count
+}
+
+/// Writes elements into `buf` with std140 array stride
+pub fn write_array<T: Std140>(elements: &[T], buf: &mut [u8]) {
+    assert!(
+        buf.len() >= array_size::<T>(elements.len()),
+        "buffer too small for {} {}s",
+        elements.len(),
+        T::GLSL_TYPE
+    );
+
+    for (e, b) in elements.iter().zip(buf.chunks_mut(T::ARRAY_STRIDE)) {
+        e.write_std140(b);
+    }
+}
+
+/// Packs elements into a newly allocated padded buffer
+pub fn array_bytes<T: Std140>(elements: &[T]) -> Vec<u8> {
+    let mut buf = vec![0u8; array_size::<T>(elements.len())];
+    write_array(elements, &mut buf);
+    buf
+}
@@ -0,0 +1,124 @@
+//! Color themes of the board, grid, background and stones
+
+use serde::Deserialize;
+
+/// Themes compiled into the executable (name, JSON)
+const BUILTIN_THEMES: &'static [(&'static str, &'static str)] = &[
+    ("default", include_str!("../assets/themes/default.json")),
+    (
+        "green-felt",
+        include_str!("../assets/themes/green-felt.json"),
+    ),
+    ("walnut", include_str!("../assets/themes/walnut.json")),
+    ("midnight", include_str!("../assets/themes/midnight.json")),
+];
+
+/// Linear RGB colors, each component in 0.0..=1.0
+#[derive(Deserialize, Clone)]
+pub struct Theme {
+    #[serde(skip)]
+    pub name: String,
+    /// Clear color around the board
+    pub background: [f32; 3],
+    /// Board frame and grid lines
+    pub board: [f32; 3],
+    /// Inside of grid cells
+    pub grid: [f32; 3],
+    pub black_stone: [f32; 3],
+    pub white_stone: [f32; 3],
+}
+impl Theme {
+    fn parse(name: &str, json: &str) -> Result<Self, String> {
+        let mut t: Theme = serde_json::from_str(json)
+            .map_err(|e| format!("Failed to parse theme {}: {}", name, e))?;
+        t.name = name.to_owned();
+        Ok(t)
+    }
+
+    /// Loads a theme file. The theme is named after the file stem.
+    pub fn load_file(path: &str) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to load theme {}: {}", path, e))?;
+        let name = std::path::Path::new(path)
+            .file_stem()
+            .map_or_else(|| path.to_owned(), |s| s.to_string_lossy().into());
+        Self::parse(&name, &json)
+    }
+}
+
+/// Built-in themes and an optional theme file, one of which is current
+pub struct Themes {
+    themes: Vec<Theme>,
+    current: usize,
+    /// Path of the theme file, reloaded on asset changes
+    file: Option<String>,
+}
+impl Themes {
+    /// `selection` is either a built-in theme name or a path to a theme file
+    pub fn new(selection: Option<&str>) -> Self {
+        let mut themes: Vec<_> = BUILTIN_THEMES
+            .iter()
+            .map(|&(name, json)| {
+                Theme::parse(name, json).unwrap_or_else(|e| panic!("{}", e))
+            })
+            .collect();
+        let mut file = None;
+        let current = match selection {
+            None => 0,
+            Some(s) => match themes.iter().position(|t| t.name == s) {
+                Some(n) => n,
+                None => {
+                    let t =
+                        Theme::load_file(s).unwrap_or_else(|e| panic!("{}", e));
+                    file = Some(s.to_owned());
+                    themes.push(t);
+                    themes.len() - 1
+                }
+            },
+        };
+
+        Themes {
+            themes,
+            current,
+            file,
+        }
+    }
+
+    pub fn current(&self) -> &Theme {
+        &self.themes[self.current]
+    }
+
+    /// Switches to the next theme (wraps around)
+    pub fn cycle(&mut self) {
+        self.current = (self.current + 1) % self.themes.len();
+        log::info!("Theme: {}", self.current().name);
+    }
+
+    /// Returns false if no theme has the name
+    pub fn select(&mut self, name: &str) -> bool {
+        match self.themes.iter().position(|t| t.name == name) {
+            Some(n) => {
+                self.current = n;
+                log::info!("Theme: {}", name);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Rereads the theme file if any.
+    /// The previous colors are kept if it fails to load.
+    pub fn reload_file(&mut self) {
+        let path = match self.file {
+            Some(ref p) => p,
+            None => return,
+        };
+        match Theme::load_file(path) {
+            Ok(t) => {
+                // the file theme is always the last one
+                *self.themes.last_mut().expect("no themes") = t;
+            }
+            Err(e) => log::warn!("{}", e),
+        }
+    }
+}
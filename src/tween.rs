@@ -0,0 +1,332 @@
+//! Time based tweens of per-cell animation parameters
+//!
+//! Tweens write float members of `CellState` directly into the board state
+//! buffer shared with scripts. Each tween belongs to a script promise, which
+//! is resolved when all of its tweens have finished.
+
+use crate::cell_state::CellState;
+use crate::std140::Std140;
+
+#[derive(Clone, Copy)]
+pub enum Easing {
+    Linear,
+    EaseInQuad,
+    EaseOutQuad,
+    EaseInOutQuad,
+    EaseOutCubic,
+    EaseOutQuart,
+    EaseOutBack,
+    EaseOutBounce,
+}
+impl Easing {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "linear" => Some(Easing::Linear),
+            "easeInQuad" => Some(Easing::EaseInQuad),
+            "easeOutQuad" => Some(Easing::EaseOutQuad),
+            "easeInOutQuad" => Some(Easing::EaseInOutQuad),
+            "easeOutCubic" => Some(Easing::EaseOutCubic),
+            "easeOutQuart" => Some(Easing::EaseOutQuart),
+            "easeOutBack" => Some(Easing::EaseOutBack),
+            "easeOutBounce" => Some(Easing::EaseOutBounce),
+            _ => None,
+        }
+    }
+
+    /// Maps linear progress 0..=1 to eased progress
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInQuad => t * t,
+            Easing::EaseOutQuad => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOutQuad => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) * 0.5
+                }
+            }
+            Easing::EaseOutCubic => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseOutQuart => 1.0 - (1.0 - t).powi(4),
+            Easing::EaseOutBack => {
+                const C1: f32 = 1.70158;
+                const C3: f32 = C1 + 1.0;
+                1.0 + C3 * (t - 1.0).powi(3) + C1 * (t - 1.0).powi(2)
+            }
+            Easing::EaseOutBounce => {
+                const N1: f32 = 7.5625;
+                const D1: f32 = 2.75;
+                if t < 1.0 / D1 {
+                    N1 * t * t
+                } else if t < 2.0 / D1 {
+                    let t = t - 1.5 / D1;
+                    N1 * t * t + 0.75
+                } else if t < 2.5 / D1 {
+                    let t = t - 2.25 / D1;
+                    N1 * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / D1;
+                    N1 * t * t + 0.984375
+                }
+            }
+        }
+    }
+}
+
+/// Byte offset of a float member of `CellState`, looked up by GLSL name
+pub fn cell_float_field(name: &str) -> Option<usize> {
+    <CellState as Std140>::FIELDS
+        .iter()
+        .find(|f| f.name == name && f.glsl_type == "float")
+        .map(|f| f.offset)
+}
+
+pub struct Tween {
+    /// (cell index, byte offset in the cell). None for plain timers.
+    pub target: Option<(usize, usize)>,
+    pub from: f32,
+    pub to: f32,
+    pub start_ms: f64,
+    pub duration_ms: f64,
+    pub easing: Easing,
+    /// Promise resolved when all tweens of it have finished
+    pub promise: u32,
+}
+impl Tween {
+    fn value_at(&self, now_ms: f64) -> f32 {
+        let t = if self.duration_ms <= 0.0 {
+            1.0
+        } else {
+            ((now_ms - self.start_ms) / self.duration_ms)
+                .max(0.0)
+                .min(1.0)
+        };
+        self.from + (self.to - self.from) * self.easing.apply(t as f32)
+    }
+    fn finished_at(&self, now_ms: f64) -> bool {
+        now_ms >= self.start_ms + self.duration_ms
+    }
+
+    fn write(&self, cells: &mut [u8], value: f32) {
+        let (cell, offset) = match self.target {
+            Some(t) => t,
+            None => return,
+        };
+        let p = cell * <CellState as Std140>::ARRAY_STRIDE + offset;
+        // buffers smaller than the board are ignored
+        if let Some(b) = cells.get_mut(p..p + 4) {
+            value.write_std140(b);
+        }
+    }
+}
+
+/// Timing options shared by animation functions
+pub struct AnimationOptions {
+    pub duration_ms: f64,
+    pub delay_ms: f64,
+    /// Delay added for each subsequent cell
+    pub stagger_ms: f64,
+    pub easing: Easing,
+}
+
+pub struct TweenScheduler {
+    tweens: Vec<Tween>,
+}
+impl TweenScheduler {
+    pub fn new() -> Self {
+        TweenScheduler { tweens: Vec::new() }
+    }
+
+    pub fn add(&mut self, tween: Tween) {
+        self.tweens.push(tween);
+    }
+
+    /// Adds tweens of a float field of cells starting after the delay, each
+    /// cell staggered after the previous one
+    pub fn add_cells(
+        &mut self,
+        now_ms: f64,
+        cells: &[usize],
+        field_offset: usize,
+        (from, to): (f32, f32),
+        options: &AnimationOptions,
+        promise: u32,
+    ) {
+        let start_ms = now_ms + options.delay_ms;
+        for (n, &cell) in cells.iter().enumerate() {
+            self.add(Tween {
+                target: Some((cell, field_offset)),
+                from,
+                to,
+                start_ms: start_ms + options.stagger_ms * n as f64,
+                duration_ms: options.duration_ms,
+                easing: options.easing,
+                promise,
+            });
+        }
+        // resolves at the next frame if there is nothing to animate
+        if cells.is_empty() {
+            self.add(Tween {
+                target: None,
+                from: 0.0,
+                to: 0.0,
+                start_ms,
+                duration_ms: 0.0,
+                easing: Easing::Linear,
+                promise,
+            });
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.tweens.is_empty()
+    }
+
+    /// Removes finished tweens after writing their final values.
+    /// Returns promises whose tweens have all finished.
+    pub fn finish(&mut self, now_ms: f64, cells: &mut [u8]) -> Vec<u32> {
+        let mut finished_promises = Vec::new();
+        for t in self.tweens.iter().filter(|t| t.finished_at(now_ms)) {
+            t.write(cells, t.to);
+            if !finished_promises.contains(&t.promise) {
+                finished_promises.push(t.promise);
+            }
+        }
+        self.tweens.retain(|t| !t.finished_at(now_ms));
+        let tweens = &self.tweens;
+        finished_promises.retain(|&p| tweens.iter().all(|t| t.promise != p));
+
+        finished_promises
+    }
+
+    /// Writes current values of running tweens.
+    /// Tweens waiting for their delay hold the initial value.
+    pub fn apply(&self, now_ms: f64, cells: &mut [u8]) {
+        for t in &self.tweens {
+            t.write(cells, t.value_at(now_ms));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EASINGS: &[&str] = &[
+        "linear",
+        "easeInQuad",
+        "easeOutQuad",
+        "easeInOutQuad",
+        "easeOutCubic",
+        "easeOutQuart",
+        "easeOutBack",
+        "easeOutBounce",
+    ];
+
+    fn flip_offset() -> usize {
+        cell_float_field("flipProgress").unwrap()
+    }
+
+    fn cells(count: usize) -> Vec<u8> {
+        vec![0; count * <CellState as Std140>::ARRAY_STRIDE]
+    }
+
+    fn read(cells: &[u8], cell: usize, offset: usize) -> f32 {
+        let p = cell * <CellState as Std140>::ARRAY_STRIDE + offset;
+        let mut b = [0; 4];
+        b.copy_from_slice(&cells[p..p + 4]);
+        f32::from_ne_bytes(b)
+    }
+
+    fn options(delay_ms: f64, stagger_ms: f64) -> AnimationOptions {
+        AnimationOptions {
+            duration_ms: 100.0,
+            delay_ms,
+            stagger_ms,
+            easing: Easing::Linear,
+        }
+    }
+
+    #[test]
+    fn easings_start_at_zero_and_end_at_one() {
+        for name in EASINGS {
+            let easing = Easing::parse(name).unwrap();
+            assert!(easing.apply(0.0).abs() < 1e-6, "{} at 0", name);
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-6, "{} at 1", name);
+        }
+        assert!(Easing::parse("easeInBounce").is_none());
+    }
+
+    #[test]
+    fn delay_and_stagger_offset_each_cell() {
+        let mut scheduler = TweenScheduler::new();
+        let offset = flip_offset();
+        let options = options(50.0, 10.0);
+        scheduler.add_cells(
+            1000.0,
+            &[0, 1, 2],
+            offset,
+            (0.0, 1.0),
+            &options,
+            1,
+        );
+        let mut buf = cells(3);
+
+        // still in the delay, holding the initial value
+        scheduler.apply(1040.0, &mut buf);
+        assert_eq!(read(&buf, 0, offset), 0.0);
+        // 50ms after the delay, each cell 10ms behind the previous one
+        scheduler.apply(1100.0, &mut buf);
+        assert_eq!(read(&buf, 0, offset), 0.5);
+        assert_eq!(read(&buf, 1, offset), 0.4);
+        assert_eq!(read(&buf, 2, offset), 0.3);
+
+        assert!(scheduler.finish(1169.0, &mut buf).is_empty());
+        assert_eq!(read(&buf, 1, offset), 1.0);
+        assert_eq!(read(&buf, 2, offset), 0.3);
+        assert_eq!(scheduler.finish(1170.0, &mut buf), vec![1]);
+        assert_eq!(read(&buf, 2, offset), 1.0);
+    }
+
+    #[test]
+    fn finish_returns_each_promise_once() {
+        let mut scheduler = TweenScheduler::new();
+        let offset = flip_offset();
+        let mut buf = cells(2);
+        scheduler.add_cells(
+            0.0,
+            &[0, 1],
+            offset,
+            (0.0, 1.0),
+            &options(0.0, 0.0),
+            1,
+        );
+        scheduler.add_cells(
+            0.0,
+            &[],
+            offset,
+            (0.0, 1.0),
+            &options(0.0, 0.0),
+            2,
+        );
+        scheduler.add_cells(
+            0.0,
+            &[1],
+            offset,
+            (1.0, 0.0),
+            &options(100.0, 0.0),
+            3,
+        );
+        assert!(scheduler.is_active());
+
+        // an empty cell list resolves at the next frame
+        assert_eq!(scheduler.finish(0.0, &mut buf), vec![2]);
+        assert_eq!(scheduler.finish(100.0, &mut buf), vec![1]);
+        assert!(scheduler.finish(150.0, &mut buf).is_empty());
+        assert!(scheduler.is_active());
+        assert_eq!(scheduler.finish(200.0, &mut buf), vec![3]);
+        assert_eq!(read(&buf, 1, offset), 0.0);
+        assert!(!scheduler.is_active());
+        assert!(scheduler.finish(300.0, &mut buf).is_empty());
+    }
+}
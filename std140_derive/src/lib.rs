@@ -0,0 +1,205 @@
+//! `#[derive(Std140)]` for structures stored in std140 uniform blocks.
+//!
+//! The generated implementation refers to `crate::std140::Std140`, so this
+//! derive is usable only inside the main crate.
+//!
+//! Expected layouts can be pinned with attributes; mismatches fail the build:
+//!
+//! ```ignore
+//! #[derive(Clone, Copy, Std140)]
+//! #[std140(size = 8, array_stride = 16)]
+//! pub struct CellState {
+//!     #[std140(offset = 0)]
+//!     pub state_flags: u32,
+//!     #[std140(offset = 4)]
+//!     pub flip_start_time: f32,
+//! }
+//! ```
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+
+#[proc_macro_derive(Std140, attributes(std140))]
+pub fn derive_std140(
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as syn::DeriveInput);
+
+    match expand(&input) {
+        Ok(t) => t.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Parses `#[std140(key = value, ...)]` attributes into integer pairs
+fn layout_attributes(
+    attrs: &[syn::Attribute],
+) -> syn::Result<Vec<(syn::Ident, syn::LitInt)>> {
+    let mut pairs = Vec::new();
+    for a in attrs.iter().filter(|a| a.path.is_ident("std140")) {
+        let list = match a.parse_meta()? {
+            syn::Meta::List(l) => l,
+            m => return Err(syn::Error::new(m.span(), "expected std140(...)")),
+        };
+        for n in list.nested {
+            match n {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(
+                    syn::MetaNameValue {
+                        path,
+                        lit: syn::Lit::Int(v),
+                        ..
+                    },
+                )) if path.get_ident().is_some() => {
+                    pairs.push((path.get_ident().unwrap().clone(), v));
+                }
+                n => {
+                    return Err(syn::Error::new(
+                        n.span(),
+                        "expected `key = integer`",
+                    ))
+                }
+            }
+        }
+    }
+
+    Ok(pairs)
+}
+
+fn camel_case(snake: &str) -> String {
+    let mut parts = snake.split('_');
+    let mut s = parts.next().unwrap_or_default().to_owned();
+    for p in parts {
+        let mut cs = p.chars();
+        if let Some(c) = cs.next() {
+            s.extend(c.to_uppercase());
+            s.push_str(cs.as_str());
+        }
+    }
+    s
+}
+
+fn expand(input: &syn::DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(f),
+            ..
+        }) => &f.named,
+        _ => {
+            return Err(syn::Error::new(
+                input.span(),
+                "Std140 can only be derived for structs with named fields",
+            ))
+        }
+    };
+    if fields.is_empty() {
+        return Err(syn::Error::new(
+            input.span(),
+            "std140 structures must have at least one field",
+        ));
+    }
+
+    let tys: Vec<_> = fields.iter().map(|f| &f.ty).collect();
+    let idents: Vec<_> =
+        fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+    let offset_vars: Vec<_> =
+        (0..fields.len()).map(|n| format_ident!("o{}", n)).collect();
+    let field_count = fields.len();
+    let glsl_names: Vec<_> =
+        idents.iter().map(|i| camel_case(&i.to_string())).collect();
+
+    // offset of each member: end of previous member rounded up to the
+    // base alignment of the member
+    let offset_exprs = tys.iter().enumerate().map(|(n, ty)| {
+        let var = &offset_vars[n];
+        if n == 0 {
+            quote! { let #var: usize = 0; }
+        } else {
+            let prev = &offset_vars[n - 1];
+            let prev_ty = tys[n - 1];
+            quote! {
+                let #var = crate::std140::round_up(
+                    #prev + <#prev_ty as crate::std140::Std140>::SIZE,
+                    <#ty as crate::std140::Std140>::ALIGN,
+                );
+            }
+        }
+    });
+    let last_ty = tys[field_count - 1];
+    let max_align = tys.iter().fold(quote! { 0 }, |acc, ty| {
+        quote! {
+            crate::std140::max(#acc, <#ty as crate::std140::Std140>::ALIGN)
+        }
+    });
+
+    let mut assertions = Vec::new();
+    for (n, f) in fields.iter().enumerate() {
+        for (key, value) in layout_attributes(&f.attrs)? {
+            if key != "offset" {
+                return Err(syn::Error::new(key.span(), "unknown key"));
+            }
+            let msg = format!(
+                "std140 offset of {}::{} does not match the expected value",
+                name, idents[n]
+            );
+            assertions.push(quote! {
+                const _: () = assert!(OFFSETS[#n] == #value, #msg);
+            });
+        }
+    }
+    for (key, value) in layout_attributes(&input.attrs)? {
+        let item = if key == "size" {
+            quote! { UNPADDED_SIZE }
+        } else if key == "array_stride" {
+            quote! { <#name as crate::std140::Std140>::ARRAY_STRIDE }
+        } else {
+            return Err(syn::Error::new(key.span(), "unknown key"));
+        };
+        let msg = format!(
+            "std140 {} of {} does not match the expected value",
+            key, name
+        );
+        assertions.push(quote! {
+            const _: () = assert!(#item == #value, #msg);
+        });
+    }
+
+    let glsl_type = name.to_string();
+    let field_indices = 0..field_count;
+    let field_indices2 = 0..field_count;
+    Ok(quote! {
+        const _: () = {
+            const OFFSETS: [usize; #field_count] = {
+                #(#offset_exprs)*
+                [#(#offset_vars),*]
+            };
+            const UNPADDED_SIZE: usize = OFFSETS[#field_count - 1]
+                + <#last_ty as crate::std140::Std140>::SIZE;
+            const ALIGN: usize = crate::std140::round_up(#max_align, 16);
+
+            impl crate::std140::Std140 for #name {
+                const ALIGN: usize = ALIGN;
+                // structures are padded to a multiple of their base alignment
+                const SIZE: usize = crate::std140::round_up(UNPADDED_SIZE, ALIGN);
+                const GLSL_TYPE: &'static str = #glsl_type;
+                const FIELDS: &'static [crate::std140::Std140Field] = &[
+                    #(crate::std140::Std140Field {
+                        name: #glsl_names,
+                        offset: OFFSETS[#field_indices],
+                        glsl_type: <#tys as crate::std140::Std140>::GLSL_TYPE,
+                    }),*
+                ];
+
+                fn write_std140(&self, buf: &mut [u8]) {
+                    #(crate::std140::Std140::write_std140(
+                        &self.#idents,
+                        &mut buf[OFFSETS[#field_indices2]..],
+                    );)*
+                }
+            }
+
+            #(#assertions)*
+        };
+    })
+}